@@ -0,0 +1,813 @@
+//! The `timers` command line tool, which reports a `Measurement` and whatever else was asked for while it ran.
+
+use crate::capture::Capture;
+use crate::cli::Args;
+use crate::marker::{self, Markers};
+use crate::sample::{self, Sampler};
+use crate::trace::{self, Gate, Tracker};
+use crate::watch::Monitor;
+use crate::{attach, check, child, clocks, export, ffi, fmt, jobs, pipeline, status, Command};
+use anyhow::Result;
+use bytesize::ByteSize;
+use std::ffi::OsString;
+use std::time::{Duration, Instant};
+use std::{env, process};
+
+const NO_DATA: &str = "-";
+/// Commands longer than this are truncated in the process tree.
+const TREE_COMMAND_WIDTH: usize = 80;
+
+/// Runs the `timers` binary, which only has to set up logging before calling this.
+pub fn run() -> ! {
+    let args = Args::parse().expect("failed to parse arguments");
+    log::trace!("{:#?}", args);
+
+    if args.check_env {
+        let findings = check::check_env();
+        for finding in &findings {
+            log::warn!("{:<18}{}", format!("{}:", finding.name), finding.message);
+            log::info!("hint:             {}", finding.hint);
+        }
+        if findings.is_empty() {
+            log::info!("check_env:        no issues found");
+        }
+        if args.args.is_empty() && args.pid.is_none() {
+            process::exit(0);
+        }
+    }
+
+    if let Some(pid) = args.pid {
+        run_attached(&args, pid);
+    }
+
+    log::info!(
+        "cmdline:          {}",
+        args.args
+            .iter()
+            .map(|os| os.to_string_lossy())
+            .collect::<Vec<_>>()
+            .join(" ")
+    );
+
+    let isolation = args.isolation.describe();
+    log::info!(
+        "isolation:        {}",
+        if isolation.is_empty() {
+            NO_DATA.into()
+        } else {
+            isolation.join(", ")
+        }
+    );
+
+    let limits = args.limits.iter().map(|l| l.to_string()).collect::<Vec<_>>();
+    log::info!(
+        "limits:           {}",
+        if limits.is_empty() {
+            NO_DATA.into()
+        } else {
+            limits.join(", ")
+        }
+    );
+    log::info!("env:              {}", args.environment.describe());
+    log::info!(
+        "chdir:            {}",
+        args.chdir.as_ref().map_or(NO_DATA.into(), |d| d.display().to_string())
+    );
+    let fmt_redirect = |r: &Option<child::Redirect>| r.as_ref().map_or(NO_DATA.into(), |r| r.to_string());
+    log::info!("stdin:            {}", fmt_redirect(&args.stdio.stdin));
+    log::info!("stdout:           {}", fmt_redirect(&args.stdio.stdout));
+    log::info!("stderr:           {}", fmt_redirect(&args.stdio.stderr));
+
+    // CPU information
+    log::info!(
+        "cpu_count:        {}",
+        ffi::cpu_count().map_or(NO_DATA.into(), |n| n.to_string())
+    );
+
+    // System memory information
+    let fmt_res = |r: Result<u64>| {
+        r.map_or_else(
+            |e| {
+                log::warn!("{}", e);
+                NO_DATA.into()
+            },
+            &fmt_bytes,
+        )
+    };
+
+    log::info!("mem_total:        {}", fmt_res(ffi::mem::memory_total()));
+    log::info!("mem_avail:        {}", fmt_res(ffi::mem::memory_available()));
+    log::info!("page_size:        {}", fmt_res(ffi::mem::page_size()));
+
+    ffi::handle_job_control().expect("Failed to handle job control");
+
+    if let Some(stages) = &args.pipeline {
+        run_pipeline(&args, stages);
+    }
+
+    let command = args.command(&args.args);
+
+    if let Some(counts) = args.scale.clone().or_else(|| args.jobs.map(|n| vec![n])) {
+        run_jobs(&args, &command, &counts);
+    }
+
+    let untraced = args
+        .untraced_run
+        .then(|| command.measure())
+        .transpose()
+        .expect("Failed to run the command without tracing");
+
+    let capture = args
+        .capture
+        .map(Capture::new)
+        .transpose()
+        .expect("Failed to create pipes for capturing output");
+    let markers = args
+        .markers
+        .map(Markers::new)
+        .transpose()
+        .expect("Failed to create pipe for markers");
+    let gate = (args.tree || args.syscalls || args.files)
+        .then(Gate::new)
+        .transpose()
+        .expect("Failed to create pipe");
+
+    // The command is told where to write markers through its environment
+    let mut spawned = command.clone();
+    if let Some((key, value)) = markers.as_ref().map(Markers::var) {
+        spawned.env(key, value);
+    }
+    let child = spawned
+        .spawn_with(|| {
+            if let Some(gate) = &gate {
+                gate.wait()?;
+            }
+            if let Some(capture) = &capture {
+                capture.redirect()?;
+            }
+            if let Some(markers) = &markers {
+                markers.inherit()?;
+            }
+            Ok(())
+        })
+        .expect("Failed to start the command");
+    let (pid, start) = (child.pid(), child.start());
+    // This log line may have an effect on short-lived programs real time, since there's a chance
+    // that the forked process will exit before this log completes
+    #[cfg(debug_assertions)]
+    log::info!("pid:              {}", pid);
+
+    let tracker = gate.map(|gate| {
+        let options = trace::Options {
+            syscalls: args.syscalls,
+            files: args.files,
+        };
+        let tracker = Tracker::attach(pid, start, options);
+        gate.open().expect("Failed to start the command");
+        tracker.expect("Failed to follow the command")
+    });
+    if let Some(e) = tracker.as_ref().and_then(Tracker::fallback) {
+        log::warn!("Failed to attach with ptrace, polling /proc instead: {:#}", e);
+    }
+    let readers = capture
+        .map(|c| c.start(start, args.time_format))
+        .transpose()
+        .expect("Failed to start capturing output");
+    let laps = markers
+        .map(|m| m.start(start, pid))
+        .transpose()
+        .expect("Failed to start reading markers");
+    let sampler = args
+        .sample
+        .as_ref()
+        .map(|o| Sampler::start(pid, start, o.interval))
+        .transpose()
+        .expect("Failed to start sampling");
+    let monitor = args
+        .watch_live
+        .then(|| Monitor::start(pid, start))
+        .and_then(|m| m.map_err(|e| log::warn!("Not showing the live view: {:#}", e)).ok());
+    let (measurement, trace) = match tracker {
+        Some(tracker) => {
+            let (waited, trace) = tracker.wait().expect("Failed waiting for child");
+            (child.finish(waited), Some(trace))
+        }
+        None => (child.wait(), None),
+    };
+    if let Some(monitor) = monitor {
+        monitor.stop().expect("Failed to restore the terminal");
+    }
+    // Like a shell, exit with 127 if the command couldn't be run at all
+    let measurement = measurement.unwrap_or_else(|e| {
+        log::error!("{:#}", e);
+        process::exit(127);
+    });
+    let real = if args.exclude_stopped {
+        measurement.running()
+    } else {
+        measurement.real
+    };
+    let output = readers.map(|r| r.join()).transpose().expect("Failed capturing output");
+    // Whatever the child wrote to the marker fd, it mustn't stop us reporting on it
+    let laps = laps
+        .map(marker::join)
+        .and_then(|laps| laps.map_err(|e| log::warn!("{:#}", e)).ok());
+    let samples = sampler.map(Sampler::stop).transpose().expect("Failed sampling");
+
+    log::trace!("{:#?}", measurement);
+
+    let (status, user, sys) = (measurement.status, measurement.user, measurement.sys);
+    let pct_cpu = 100.0 * (user.as_secs_f64() + sys.as_secs_f64()) / real.as_secs_f64();
+    let limit_reasons = child::explain_limits(&args.limits, status, user + sys, measurement.max_rss);
+    let run = export::Run {
+        argv: args.args.iter().map(|a| a.to_string_lossy().into_owned()).collect(),
+        pid,
+        status,
+        real,
+        user,
+        sys,
+        tree: trace.as_ref().map(|t| &t.tree),
+        laps: laps.as_deref(),
+        samples: samples.as_deref(),
+    };
+
+    let fmt = fmt::duration_formatter(args.time_format);
+    let real = fmt(real);
+    let user = fmt(user);
+    let sys = fmt(sys);
+
+    // Exit code
+    let decoded = measurement.exit();
+    match decoded {
+        Some(status::Status::Exited(code)) => log::info!("exit code:        {}", code),
+        _ => log::info!("exit code:        -"),
+    }
+    // Signal number
+    match decoded {
+        Some(status::Status::Signaled { signal, core_dumped }) => {
+            log::info!("term_signal:      {}", status::describe_signal(signal));
+            log::info!(
+                "signal_action:    {}",
+                status::default_action(signal).map_or(NO_DATA.into(), |a| a.to_string())
+            );
+            log::info!("core_dumped:      {}", if core_dumped { "yes" } else { "no" });
+            if core_dumped {
+                let cwd = match &args.chdir {
+                    Some(dir) => env::current_dir()
+                        .map(|cwd| cwd.join(dir))
+                        .unwrap_or_else(|_| dir.clone()),
+                    None => env::current_dir().unwrap_or_default(),
+                };
+                // The kernel names it after the executable it was running, which is read before it's reaped. If
+                // that failed, it's the command we ran unless that exec'd something else.
+                let comm = measurement
+                    .comm
+                    .clone()
+                    .unwrap_or_else(|| status::comm(&args.args[0].to_string_lossy()));
+                let dumped = status::Dumped {
+                    pid,
+                    signal,
+                    comm: &comm,
+                    cwd: &cwd,
+                };
+                match status::core_file(&dumped) {
+                    Ok(location) => log::info!("core_file:        {}", location),
+                    Err(e) => log::warn!("{:#}", e),
+                }
+            }
+        }
+        _ => log::info!("term_signal:      -"),
+    }
+    let stopped = &measurement.stopped;
+    if !stopped.signals.is_empty() {
+        let signals = stopped
+            .signals
+            .iter()
+            .map(|s| status::describe_signal(*s))
+            .collect::<Vec<_>>();
+        log::info!("stop_signals:     {}", signals.join(", "));
+    }
+    let return_code = measurement.exit_code(args.signal_exit);
+    for reason in limit_reasons {
+        log::warn!("limit_exceeded:   {}", reason);
+    }
+
+    // Timers
+    // SAFETY: `None` is only returned if the iterator is empty
+    let len = *[real.len(), user.len(), sys.len()].iter().max().unwrap() - 1;
+    log::info!("real:             {:>width$}", real, width = len);
+    log::info!("user:             {:>width$}", user, width = len);
+    log::info!("sys:              {:>width$}", sys, width = len);
+    log::info!(
+        "stopped_time:     {}{}",
+        fmt(stopped.time),
+        if args.exclude_stopped {
+            " (excluded from real)"
+        } else {
+            ""
+        }
+    );
+    let fmt_timestamp = |r: clocks::Reading| r.realtime.map_or(NO_DATA.into(), fmt::timestamp);
+    log::info!("started_at:       {}", fmt_timestamp(measurement.started));
+    log::info!("ended_at:         {}", fmt_timestamp(measurement.ended));
+    for drift in measurement.drift() {
+        match drift {
+            clocks::Drift::Suspended(d) => {
+                log::warn!(
+                    "suspended:        the system was suspended for {}, which isn't included in real",
+                    fmt(d)
+                )
+            }
+            clocks::Drift::Stepped(secs) => {
+                log::warn!(
+                    "clock_stepped:    the wall clock was changed by {:+.6}s, so ended_at may be off",
+                    secs
+                )
+            }
+            clocks::Drift::Slewed(ppm) => log::warn!(
+                "clock_slewed:     the clock's rate was being adjusted by {:+.0}ppm, which real includes",
+                ppm
+            ),
+        }
+    }
+    log::info!("percent_cpu:      {:.4}%", pct_cpu);
+    if let Some(untraced) = &untraced {
+        let real = if args.exclude_stopped {
+            untraced.running()
+        } else {
+            untraced.real
+        };
+        log::info!("untraced_real:    {}", fmt(real));
+        log::info!("untraced_user:    {}", fmt(untraced.user));
+        log::info!("untraced_sys:     {}", fmt(untraced.sys));
+    }
+    // Maximum resident set size (approximate maximum memory used by the process)
+    log::info!("max_rss:          {}", fmt_bytes(measurement.max_rss));
+    // Page faults
+    log::info!("hard_page_faults: {}", measurement.hard_page_faults);
+    log::info!("soft_page_faults: {}", measurement.soft_page_faults);
+    // Number of time the filesystem had to perform real IO (doesn't account for caches)
+    log::info!("disk_inputs:      {}", measurement.disk_inputs);
+    log::info!("disk_outputs:     {}", measurement.disk_outputs);
+    // Context switches
+    log::info!("voluntary_csw:    {}", measurement.voluntary_csw);
+    log::info!("involuntary_csw:  {}", measurement.involuntary_csw);
+
+    // Laps
+    if let Some(laps) = &laps {
+        let mut rows = vec![vec!["name".into(), "at".into(), "delta".into()]];
+        let with_cpu = laps.iter().any(|l| l.cpu.is_some());
+        if with_cpu {
+            rows[0].extend(["user".into(), "sys".into()]);
+        }
+        let mut previous = Duration::ZERO;
+        for lap in laps {
+            let mut row = vec![lap.name.clone(), fmt(lap.at), fmt(lap.at - previous)];
+            if with_cpu {
+                let (user, sys) = lap
+                    .cpu
+                    .map_or((NO_DATA.into(), NO_DATA.into()), |(u, s)| (fmt(u), fmt(s)));
+                row.extend([user, sys]);
+            }
+            rows.push(row);
+            previous = lap.at;
+        }
+        if laps.is_empty() {
+            log::info!("laps:             -");
+        } else {
+            for line in fmt::table(&rows) {
+                log::info!("laps:             {}", line);
+            }
+        }
+    }
+
+    // Process tree
+    if let Some(tree) = trace.as_ref().filter(|_| args.tree).map(|t| &t.tree) {
+        log::info!(
+            "tree_method:      {}",
+            match tree.method {
+                trace::Method::Ptrace => "ptrace",
+                trace::Method::Poll => "polling /proc (short-lived processes may be missed)",
+            }
+        );
+        let mut rows = vec![["command", "pid", "start", "end", "user", "sys", "max_rss"]
+            .map(String::from)
+            .to_vec()];
+        for (depth, process) in tree.walk() {
+            let mut command = process.argv.join(" ");
+            if command.chars().count() > TREE_COMMAND_WIDTH {
+                command = command.chars().take(TREE_COMMAND_WIDTH - 1).chain(Some('…')).collect();
+            }
+            rows.push(vec![
+                format!("{}{}", "  ".repeat(depth), command),
+                process.pid.to_string(),
+                fmt(process.start),
+                process.end.map_or(NO_DATA.into(), &fmt),
+                fmt(process.user),
+                fmt(process.sys),
+                process
+                    .max_rss
+                    .map_or(NO_DATA.into(), |b| ByteSize(b).to_string_as(true)),
+            ]);
+        }
+        for line in fmt::table(&rows) {
+            log::info!("tree:             {}", line);
+        }
+    }
+
+    // System calls
+    if let Some(syscalls) = trace.as_ref().and_then(|t| t.syscalls.as_ref()) {
+        let total = syscalls.iter().map(|s| s.time).sum::<Duration>();
+        let mut rows = vec![["syscall", "calls", "errors", "time", "percent"]
+            .map(String::from)
+            .to_vec()];
+        for syscall in syscalls {
+            rows.push(vec![
+                syscall.name(),
+                syscall.calls.to_string(),
+                syscall.errors.to_string(),
+                fmt(syscall.time),
+                format!(
+                    "{:.2}%",
+                    100.0 * syscall.time.as_secs_f64() / total.as_secs_f64().max(f64::EPSILON)
+                ),
+            ]);
+        }
+        for line in fmt::table(&rows) {
+            log::info!("syscalls:         {}", line);
+        }
+        log::warn!(
+            "syscalls:         times include the overhead of tracing, {}",
+            if untraced.is_some() {
+                "see untraced_* for the times of the untraced run"
+            } else {
+                "use --untraced-run to also time the command without it"
+            }
+        );
+    }
+
+    // Files
+    if let Some((files, method)) = trace.as_ref().and_then(|t| Some((t.files.as_ref()?, t.tree.method))) {
+        // Bytes are only known if we saw the system calls
+        let total = |f: fn(&trace::File) -> Option<u64>| {
+            files
+                .iter()
+                .map(f)
+                .sum::<Option<u64>>()
+                .filter(|_| method == trace::Method::Ptrace)
+        };
+        log::info!("files_unique:     {}", files.len());
+        log::info!("files_opens:      {}", files.iter().map(|f| f.opens).sum::<u64>());
+        log::info!("files_stats:      {}", files.iter().map(|f| f.stats).sum::<u64>());
+        log::info!(
+            "files_read:       {}",
+            total(|f| f.read).map_or(NO_DATA.into(), &fmt_bytes)
+        );
+        log::info!(
+            "files_written:    {}",
+            total(|f| f.written).map_or(NO_DATA.into(), &fmt_bytes)
+        );
+        let mut rows = vec![["path", "opens", "stats", "execs", "errors", "read", "written"]
+            .map(String::from)
+            .to_vec()];
+        for file in files {
+            let fmt_size = |b: Option<u64>| b.map_or(NO_DATA.into(), |b| ByteSize(b).to_string_as(true));
+            rows.push(vec![
+                file.path.display().to_string(),
+                file.opens.to_string(),
+                file.stats.to_string(),
+                file.execs.to_string(),
+                file.errors.to_string(),
+                fmt_size(file.read),
+                fmt_size(file.written),
+            ]);
+        }
+        if files.is_empty() {
+            log::info!("files:            -");
+        } else {
+            for line in fmt::table(&rows) {
+                log::info!("files:            {}", line);
+            }
+        }
+    }
+
+    // Timeline
+    if let (Some(options), Some(samples)) = (&args.sample, &samples) {
+        report_samples(options, samples, &fmt);
+    }
+
+    // Captured output
+    if let Some(output) = output {
+        let fmt_time = |d: Option<_>| d.map_or(NO_DATA.into(), &fmt);
+        log::info!("stdout_bytes:     {}", output.stdout.bytes);
+        log::info!("stdout_lines:     {}", output.stdout.lines);
+        log::info!("stderr_bytes:     {}", output.stderr.bytes);
+        log::info!("stderr_lines:     {}", output.stderr.lines);
+        log::info!("first_byte:       {}", fmt_time(output.stdout.first_byte));
+        log::info!("last_output:      {}", fmt_time(output.last_output()));
+        if let (Some(stdout), Some(stderr)) = (output.stdout.hash, output.stderr.hash) {
+            log::info!("stdout_hash:      {:016x}", stdout);
+            log::info!("stderr_hash:      {:016x}", stderr);
+        }
+        if args.capture.is_some_and(|c| c.phase_summary) {
+            for gap in &output.gaps {
+                log::info!(
+                    "gap:              {} before {:?} (after {})",
+                    fmt(gap.duration),
+                    gap.next,
+                    gap.previous.as_ref().map_or("start".into(), |p| format!("{:?}", p))
+                );
+            }
+        }
+    }
+
+    // Exports
+    if let Some(path) = &args.export_trace {
+        match export::chrome::write(path, &run) {
+            Ok(()) => log::info!("trace_file:       {}", path.display()),
+            Err(e) => log::warn!("{:#}", e),
+        }
+    }
+    if let Some(path) = &args.export_html {
+        match export::html::write(path, &run, &export::fingerprint(&command)) {
+            Ok(()) => log::info!("html_file:        {}", path.display()),
+            Err(e) => log::warn!("{:#}", e),
+        }
+    }
+
+    // Exit with either the status code or the signal number of the forked process
+    process::exit(return_code);
+}
+
+/// Waits for a process we didn't start to terminate, reports what could be seen of it and exits.
+fn run_attached(args: &Args, pid: libc::pid_t) -> ! {
+    let fmt = fmt::duration_formatter(args.time_format);
+    log::info!("pid:              {}", pid);
+
+    let start = Instant::now();
+    let sampler = args
+        .sample
+        .as_ref()
+        .map(|o| Sampler::start(pid, start, o.interval))
+        .transpose()
+        .expect("Failed to start sampling");
+    let monitor = args
+        .watch_live
+        .then(|| Monitor::start(pid, start))
+        .and_then(|m| m.map_err(|e| log::warn!("Not showing the live view: {:#}", e)).ok());
+    let observed = attach::wait(pid, start);
+    if let Some(monitor) = monitor {
+        monitor.stop().expect("Failed to restore the terminal");
+    }
+    let observed = observed.expect("Failed to follow the process");
+    let samples = sampler.map(Sampler::stop).transpose().expect("Failed sampling");
+
+    let fmt_opt = |n: Option<u64>| n.map_or(NO_DATA.into(), |n| n.to_string());
+    log::info!("cmdline:          {}", observed.argv.join(" "));
+    log::info!("running_for:      {} (before attaching)", fmt(observed.age));
+    log::info!("exit code:        -");
+    log::info!("term_signal:      -");
+    log::info!("real:             {} (since attaching)", fmt(observed.real));
+    log::info!("user:             {}", fmt(observed.user));
+    log::info!("sys:              {}", fmt(observed.sys));
+    log::info!("attached_user:    {}", fmt(observed.attached_user));
+    log::info!("attached_sys:     {}", fmt(observed.attached_sys));
+    log::info!(
+        "percent_cpu:      {:.4}%",
+        100.0 * (observed.attached_user + observed.attached_sys).as_secs_f64() / observed.real.as_secs_f64()
+    );
+    log::info!(
+        "max_rss:          {}",
+        observed.max_rss.map_or(NO_DATA.into(), fmt_bytes)
+    );
+    log::info!("hard_page_faults: {}", observed.majflt);
+    log::info!("soft_page_faults: {}", observed.minflt);
+    log::info!("disk_inputs:      -");
+    log::info!("disk_outputs:     -");
+    log::info!(
+        "disk_read:        {}",
+        observed.read_bytes.map_or(NO_DATA.into(), fmt_bytes)
+    );
+    log::info!(
+        "disk_written:     {}",
+        observed.write_bytes.map_or(NO_DATA.into(), fmt_bytes)
+    );
+    log::info!("voluntary_csw:    {}", fmt_opt(observed.voluntary_csw));
+    log::info!("involuntary_csw:  {}", fmt_opt(observed.involuntary_csw));
+    if let (Some(options), Some(samples)) = (&args.sample, &samples) {
+        report_samples(options, samples, &fmt);
+    }
+
+    // Explain the gaps, since they'd be filled in if we'd started the process ourselves
+    log::warn!("unavailable:      exit code and signal, only the parent of a process can wait for it");
+    log::warn!("unavailable:      disk_inputs and disk_outputs, they're only counted by wait4");
+    if !observed.exact {
+        log::warn!(
+            "unavailable:      the final times and page faults, those shown were read up to {} before it exited",
+            fmt(attach::INTERVAL)
+        );
+    }
+    if observed.max_rss.is_none() {
+        log::warn!("unavailable:      max_rss, the process exited before it could be read");
+    }
+    if observed.read_bytes.is_none() {
+        log::warn!(
+            "unavailable:      disk_read and disk_written, /proc/{}/io is only readable by its owner",
+            pid
+        );
+    }
+    if observed.voluntary_csw.is_none() {
+        log::warn!("unavailable:      voluntary_csw and involuntary_csw, the process exited before they could be read");
+    }
+
+    process::exit(0);
+}
+
+/// Reports a summary of the samples, and writes them to a file if asked to.
+fn report_samples(options: &sample::Options, samples: &[sample::Sample], fmt: &impl Fn(Duration) -> String) {
+    log::info!("samples:          {} (every {})", samples.len(), fmt(options.interval));
+    if let Some(summary) = sample::summarise(samples) {
+        log::info!("avg_cpu:          {:.4}%", summary.avg_cpu_pct);
+        log::info!("peak_rss:         {}", fmt_bytes(summary.peak_rss));
+        log::info!(
+            "near_peak_rss:    {} (above {:.0}% of peak)",
+            fmt(summary.near_peak),
+            100.0 * sample::NEAR_PEAK
+        );
+    }
+    if let Some(path) = &options.output {
+        match sample::write(path, samples) {
+            Ok(()) => log::info!("samples_file:     {}", path.display()),
+            Err(e) => log::warn!("{:#}", e),
+        }
+    }
+}
+
+/// Runs copies of the command concurrently for each of the job counts, reports on them and exits.
+fn run_jobs(args: &Args, command: &Command, counts: &[u32]) -> ! {
+    let fmt = fmt::duration_formatter(args.time_format);
+    let batches = counts
+        .iter()
+        .map(|n| jobs::run(*n, command))
+        .collect::<Result<Vec<_>>>()
+        .expect("Failed to run jobs");
+
+    if let [batch] = &batches[..] {
+        let mut rows = vec![["pid", "exit", "real", "user", "sys", "max_rss"]
+            .map(String::from)
+            .to_vec()];
+        for instance in &batch.instances {
+            rows.push(vec![
+                instance.pid.to_string(),
+                fmt_status(instance.status),
+                fmt(instance.real),
+                fmt(instance.user),
+                fmt(instance.sys),
+                ByteSize(instance.max_rss).to_string_as(true),
+            ]);
+        }
+        log::info!("jobs:             {}", batch.jobs());
+        log::info!("wall:             {}", fmt(batch.wall));
+        log::info!("throughput:       {:.4}/s", batch.throughput());
+        for line in fmt::table(&rows) {
+            log::info!("instances:        {}", line);
+        }
+    } else {
+        let mut rows = vec![["jobs", "wall", "throughput", "speedup", "efficiency"]
+            .map(String::from)
+            .to_vec()];
+        for row in jobs::scaling(&batches) {
+            rows.push(vec![
+                row.jobs.to_string(),
+                fmt(row.wall),
+                format!("{:.4}/s", row.throughput),
+                format!("{:.2}x", row.speedup),
+                format!("{:.1}%", 100.0 * row.efficiency),
+            ]);
+        }
+        for line in fmt::table(&rows) {
+            log::info!("scale:            {}", line);
+        }
+    }
+
+    let failed = batches
+        .iter()
+        .flat_map(|b| &b.instances)
+        .filter(|i| !libc::WIFEXITED(i.status) || libc::WEXITSTATUS(i.status) != 0)
+        .collect::<Vec<_>>();
+    for instance in &failed {
+        log::warn!(
+            "failed:           pid {} exited with {}",
+            instance.pid,
+            fmt_status(instance.status)
+        );
+    }
+
+    // Exit like the first copy that failed, or with 0 if they all succeeded
+    process::exit(
+        failed
+            .first()
+            .map_or(0, |i| status::exit_code(i.status, args.signal_exit)),
+    );
+}
+
+/// Runs each stage of the pipeline connected to the next, reports on them and exits.
+fn run_pipeline(args: &Args, stages: &[Vec<OsString>]) -> ! {
+    let fmt = fmt::duration_formatter(args.time_format);
+    // The redirections are the pipeline's, rather than each stage's
+    let commands = stages
+        .iter()
+        .map(|argv| {
+            let mut command = args.command(argv);
+            command.stdio(Default::default());
+            command
+        })
+        .collect::<Vec<_>>();
+    let instances = pipeline::run(&commands, &args.stdio).expect("Failed to run the pipeline");
+
+    let total_user = instances.iter().map(|i| i.user).sum::<Duration>();
+    let total_sys = instances.iter().map(|i| i.sys).sum::<Duration>();
+    let wall = instances.iter().map(|i| i.real).max().unwrap_or_default();
+    let pct_cpu = |user: Duration, sys: Duration, real: Duration| {
+        format!(
+            "{:.1}%",
+            100.0 * (user + sys).as_secs_f64() / real.as_secs_f64().max(f64::EPSILON)
+        )
+    };
+    let mut rows = vec![[
+        "stage", "command", "pid", "exit", "real", "user", "sys", "cpu", "max_rss",
+    ]
+    .map(String::from)
+    .to_vec()];
+    for (i, (instance, argv)) in instances.iter().zip(stages).enumerate() {
+        let mut command = argv.iter().map(|a| a.to_string_lossy()).collect::<Vec<_>>().join(" ");
+        if command.chars().count() > TREE_COMMAND_WIDTH {
+            command = command.chars().take(TREE_COMMAND_WIDTH - 1).chain(Some('…')).collect();
+        }
+        rows.push(vec![
+            (i + 1).to_string(),
+            command,
+            instance.pid.to_string(),
+            fmt_status(instance.status),
+            fmt(instance.real),
+            fmt(instance.user),
+            fmt(instance.sys),
+            pct_cpu(instance.user, instance.sys, instance.real),
+            ByteSize(instance.max_rss).to_string_as(true),
+        ]);
+    }
+    rows.push(vec![
+        "total".into(),
+        String::new(),
+        String::new(),
+        String::new(),
+        fmt(wall),
+        fmt(total_user),
+        fmt(total_sys),
+        pct_cpu(total_user, total_sys, wall),
+        String::new(),
+    ]);
+    for line in fmt::table(&rows) {
+        log::info!("stages:           {}", line);
+    }
+    log::info!(
+        "bottleneck:       {}",
+        pipeline::bottleneck(&instances).map_or(NO_DATA.into(), |i| {
+            let used = instances[i].user + instances[i].sys;
+            format!(
+                "stage {} ({:.1}% of the pipeline's cpu time)",
+                i + 1,
+                100.0 * used.as_secs_f64() / (total_user + total_sys).as_secs_f64()
+            )
+        })
+    );
+
+    let (last, rest) = instances.split_last().expect("The pipeline has at least one stage");
+    for (i, instance) in rest.iter().enumerate() {
+        if !libc::WIFEXITED(instance.status) || libc::WEXITSTATUS(instance.status) != 0 {
+            log::warn!(
+                "failed:           stage {} exited with {}",
+                i + 1,
+                fmt_status(instance.status)
+            );
+        }
+    }
+
+    // Like a shell, the pipeline exits like its last stage
+    process::exit(status::exit_code(last.status, args.signal_exit));
+}
+
+/// The exit code of a terminated process, or the signal which killed it.
+fn fmt_status(status: i32) -> String {
+    match status::Status::decode(status) {
+        Some(status::Status::Exited(code)) => code.to_string(),
+        Some(status::Status::Signaled { signal, .. }) => {
+            status::signal_name(signal).unwrap_or_else(|| format!("signal {}", signal))
+        }
+        _ => NO_DATA.into(),
+    }
+}
+
+fn fmt_bytes(b: u64) -> String {
+    format!("{} ({})", b, ByteSize(b).to_string_as(true))
+}
@@ -0,0 +1,143 @@
+//! Following a process we didn't start. We aren't its parent so we can't `wait4` for it, and instead read what we can
+//! from procfs until it terminates.
+
+use anyhow::{bail, Result};
+use std::time::{Duration, Instant};
+
+/// How often the process is read while we wait for it to terminate.
+pub const INTERVAL: Duration = Duration::from_millis(50);
+
+/// What could be seen of the process, values which couldn't be read are `None`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Observed {
+    pub argv: Vec<String>,
+    /// How long the process had been running for when we attached to it.
+    pub age: Duration,
+    /// From attaching to the process until it terminated.
+    pub real: Duration,
+    /// Times used since the process started, including the children it has waited for.
+    pub user: Duration,
+    pub sys: Duration,
+    /// Times used while we were attached.
+    pub attached_user: Duration,
+    pub attached_sys: Duration,
+    /// Peak resident set size in bytes.
+    pub max_rss: Option<u64>,
+    pub minflt: u64,
+    pub majflt: u64,
+    pub voluntary_csw: Option<u64>,
+    pub involuntary_csw: Option<u64>,
+    /// Bytes read from and written to storage, only readable by the process's owner.
+    pub read_bytes: Option<u64>,
+    pub write_bytes: Option<u64>,
+    /// Whether the times are final. Otherwise they're from the last read before it terminated, and the time it used
+    /// after that is missing.
+    pub exact: bool,
+}
+
+/// Waits for the process to terminate, reading it every `INTERVAL` in the meantime. `start` is when we attached.
+#[cfg(target_os = "linux")]
+pub fn wait(pid: libc::pid_t, start: Instant) -> Result<Observed> {
+    use crate::ffi::{self, proc};
+    use anyhow::Context;
+    use nix::errno::Errno;
+    use nix::poll::{poll, PollFd, PollFlags};
+    use std::os::unix::io::AsRawFd;
+
+    let pidfd = ffi::pidfd_open(pid).with_context(|| format!("Failed to attach to {}", pid))?;
+    let first = proc::Stat::read(pid)?;
+    let age = proc::uptime()?.saturating_sub(first.start_time);
+    let argv = proc::cmdline(pid)
+        .ok()
+        .filter(|a| !a.is_empty())
+        .unwrap_or_else(|| vec![first.comm.clone()]);
+
+    let mut last = first.clone();
+    let mut status = proc::Status::read(pid).ok();
+    let mut max_rss = status.as_ref().and_then(|s| s.vm_hwm);
+    let mut io = proc::Io::read(pid).ok();
+    // Only reads of the same process count, in case its pid is reused once it's gone
+    let same = |stat: &proc::Stat| stat.start_time == first.start_time;
+    let real = loop {
+        let mut fds = [PollFd::new(pidfd.as_raw_fd(), PollFlags::POLLIN)];
+        match poll(&mut fds, INTERVAL.as_millis() as i32) {
+            Ok(0) | Err(nix::Error::Sys(Errno::EINTR)) => {}
+            Ok(_) => break start.elapsed(),
+            Err(e) => bail!("Failed waiting for {}: {}", pid, e),
+        }
+
+        if let Some(stat) = proc::Stat::read(pid).ok().filter(same) {
+            last = stat;
+            if let Ok(s) = proc::Status::read(pid) {
+                max_rss = max_rss.max(s.vm_hwm);
+                status = Some(s);
+            }
+            io = proc::Io::read(pid).ok().or(io);
+        }
+    };
+
+    // Until its parent reaps it the process is a zombie, whose stat has the times it used up to the end
+    let exact = match proc::Stat::read(pid).ok().filter(same) {
+        Some(stat) => {
+            last = stat;
+            true
+        }
+        None => false,
+    };
+
+    Ok(Observed {
+        argv,
+        age,
+        real,
+        user: last.utime + last.cutime,
+        sys: last.stime + last.cstime,
+        attached_user: (last.utime + last.cutime).saturating_sub(first.utime + first.cutime),
+        attached_sys: (last.stime + last.cstime).saturating_sub(first.stime + first.cstime),
+        max_rss,
+        minflt: last.minflt,
+        majflt: last.majflt,
+        voluntary_csw: status.as_ref().map(|s| s.voluntary_csw),
+        involuntary_csw: status.as_ref().map(|s| s.involuntary_csw),
+        read_bytes: io.as_ref().map(|io| io.read_bytes),
+        write_bytes: io.as_ref().map(|io| io.write_bytes),
+        exact,
+    })
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn wait(_: libc::pid_t, _: Instant) -> Result<Observed> {
+    bail!("Attaching to a running process is only supported on Linux")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::Command;
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_wait() {
+        use crate::status::Status;
+
+        let child = Command::new("sleep").arg("0.3").spawn().unwrap();
+        let pid = child.pid();
+        let observed = wait(pid, Instant::now()).unwrap();
+        assert_eq!(observed.argv, ["sleep", "0.3"]);
+        assert!(observed.real >= Duration::from_millis(200), "{:?}", observed.real);
+        assert!(observed.real < Duration::from_secs(5), "{:?}", observed.real);
+        // We're its parent and haven't reaped it yet, so its final times could be read
+        assert!(observed.exact);
+
+        let measurement = child.wait().unwrap();
+        assert_eq!(measurement.exit(), Some(Status::Exited(0)));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_wait_missing() {
+        // Once it's reaped the pid doesn't exist, until it's reused
+        let pid = Command::new("true").measure().unwrap().pid;
+        let e = wait(pid, Instant::now()).unwrap_err();
+        assert_eq!(e.to_string(), format!("Failed to attach to {}", pid));
+    }
+}
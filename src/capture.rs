@@ -0,0 +1,452 @@
+//! Capturing the child's stdout and stderr by piping them through us.
+
+use crate::ffi::{cloexec_pipe, cvt};
+use crate::fmt;
+use crate::fmt::TimeFormat;
+use anyhow::{anyhow, Context, Result};
+use nix::unistd::close;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::os::unix::io::{FromRawFd, RawFd};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+/// How much of each line is kept when recording lines for the phase summary.
+const MAX_LINE_TEXT: usize = 80;
+/// How many of the largest gaps between lines of output to keep for the phase summary.
+const PHASE_SUMMARY_GAPS: usize = 5;
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Options {
+    /// Also write the captured output to our own stdout and stderr.
+    pub tee: bool,
+    /// Hash the captured output, so runs can be checked for identical results.
+    pub hash: bool,
+    /// Prefix each line written to the terminal with the time since the start of the measurement.
+    pub timestamp_lines: bool,
+    /// Record when each line was written, so the largest gaps between them can be reported.
+    pub phase_summary: bool,
+}
+
+/// A period of time without any new lines of output.
+#[derive(Debug, PartialEq)]
+pub struct Gap {
+    pub duration: Duration,
+    /// The line before the gap, `None` if the gap is from the start of the measurement.
+    pub previous: Option<String>,
+    /// The line after the gap.
+    pub next: String,
+}
+
+/// What was written to one of the child's output streams.
+#[derive(Debug)]
+pub struct Stream {
+    pub bytes: u64,
+    /// Number of lines, including a final line without a trailing newline.
+    pub lines: u64,
+    /// Time from the start of the measurement until the first byte was read.
+    pub first_byte: Option<Duration>,
+    /// Time from the start of the measurement until the last byte was read.
+    pub last_byte: Option<Duration>,
+    /// FNV-1a hash of everything that was written.
+    pub hash: Option<u64>,
+}
+
+#[derive(Debug)]
+pub struct Output {
+    pub stdout: Stream,
+    pub stderr: Stream,
+    /// The largest gaps between lines written to either stream, largest first. Only recorded for the phase summary.
+    pub gaps: Vec<Gap>,
+}
+
+impl Output {
+    /// Time from the start of the measurement until the last output on either stream.
+    pub fn last_output(&self) -> Option<Duration> {
+        self.stdout.last_byte.max(self.stderr.last_byte)
+    }
+}
+
+/// Pipes which are created before forking, and connected to the child's stdout and stderr.
+pub struct Capture {
+    options: Options,
+    stdout: (RawFd, RawFd),
+    stderr: (RawFd, RawFd),
+}
+
+impl Capture {
+    pub fn new(options: Options) -> Result<Capture> {
+        let stdout = cloexec_pipe()?;
+        let stderr = cloexec_pipe()?;
+        Ok(Capture {
+            options,
+            stdout,
+            stderr,
+        })
+    }
+
+    /// Connects the write ends of the pipes to stdout and stderr, called in the child.
+    pub fn redirect(&self) -> io::Result<()> {
+        // SAFETY: dup2 only operates on file descriptors
+        cvt(unsafe { libc::dup2(self.stdout.1, libc::STDOUT_FILENO) })?;
+        cvt(unsafe { libc::dup2(self.stderr.1, libc::STDERR_FILENO) })?;
+        Ok(())
+    }
+
+    /// Starts reading from the pipes, called in the parent after forking.
+    pub fn start(self, start: Instant, time_format: Option<TimeFormat>) -> Result<Readers> {
+        // The child has its own copies of the write ends, we need to close ours so we see EOF when it exits
+        close(self.stdout.1)?;
+        close(self.stderr.1)?;
+
+        let options = self.options;
+        let phases = options.phase_summary.then(|| Arc::new(Mutex::new(Phases::default())));
+        let reader = |fd, tee: Box<dyn Write + Send>, stream| Reader {
+            start,
+            tee: if options.tee { Some(tee) } else { None },
+            hash: if options.hash { Some(FNV_OFFSET_BASIS) } else { None },
+            stamp: if options.timestamp_lines {
+                Some(Box::new(fmt::duration_formatter(time_format)))
+            } else {
+                None
+            },
+            tracker: LineTracker::new(phases.clone().map(|phases| (phases, stream))),
+            fd,
+        };
+
+        let stdout = reader(self.stdout.0, Box::new(io::stdout()), STDOUT).spawn("stdout")?;
+        let stderr = reader(self.stderr.0, Box::new(io::stderr()), STDERR).spawn("stderr")?;
+        Ok(Readers { stdout, stderr, phases })
+    }
+}
+
+pub struct Readers {
+    stdout: JoinHandle<io::Result<Stream>>,
+    stderr: JoinHandle<io::Result<Stream>>,
+    phases: Option<Arc<Mutex<Phases>>>,
+}
+
+impl Readers {
+    /// Waits until both streams are closed, which may be after the child exits if it passed them on to others.
+    pub fn join(self) -> Result<Output> {
+        let join = |handle: JoinHandle<io::Result<Stream>>| {
+            handle
+                .join()
+                .map_err(|_| anyhow!("Output reader thread panicked"))?
+                .context("Failed reading output")
+        };
+
+        Ok(Output {
+            stdout: join(self.stdout)?,
+            stderr: join(self.stderr)?,
+            gaps: self.phases.map_or(vec![], |phases| lock(&phases).gaps()),
+        })
+    }
+}
+
+/// Reads one of the child's output streams until it's closed.
+struct Reader {
+    fd: RawFd,
+    start: Instant,
+    tee: Option<Box<dyn Write + Send>>,
+    hash: Option<u64>,
+    stamp: Option<Box<dyn Fn(Duration) -> String + Send>>,
+    tracker: LineTracker,
+}
+
+impl Reader {
+    fn spawn(self, name: &str) -> Result<JoinHandle<io::Result<Stream>>> {
+        let handle = thread::Builder::new()
+            .name(format!("capture-{}", name))
+            .spawn(move || self.read())?;
+
+        Ok(handle)
+    }
+
+    fn read(mut self) -> io::Result<Stream> {
+        // SAFETY: we own the read end of the pipe, and nothing else closes it
+        let mut file = unsafe { File::from_raw_fd(self.fd) };
+        let mut stream = Stream {
+            bytes: 0,
+            lines: 0,
+            first_byte: None,
+            last_byte: None,
+            hash: None,
+        };
+        let mut last = b'\n';
+        let mut buf = [0; 8192];
+        let mut stamped = vec![];
+        loop {
+            let n = match file.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+
+            let elapsed = self.start.elapsed();
+            stream.first_byte.get_or_insert(elapsed);
+            stream.last_byte = Some(elapsed);
+
+            let chunk = &buf[..n];
+            stream.bytes += n as u64;
+            stream.lines += chunk.iter().filter(|&&b| b == b'\n').count() as u64;
+            last = chunk[n - 1];
+            if let Some(hash) = self.hash.as_mut() {
+                *hash = fnv1a(*hash, chunk);
+            }
+
+            stamped.clear();
+            let prefix = self.stamp.as_ref().map(|fmt| format!("[{}] ", fmt(elapsed)));
+            self.tracker.process(chunk, elapsed, prefix.as_deref(), &mut stamped);
+            if let Some(tee) = self.tee.as_mut() {
+                tee.write_all(if prefix.is_some() { &stamped } else { chunk })?;
+                tee.flush()?;
+            }
+        }
+
+        if last != b'\n' {
+            stream.lines += 1;
+        }
+        stream.hash = self.hash;
+        Ok(stream)
+    }
+}
+
+/// Finds the start of each line in a stream, which may be split across many reads.
+struct LineTracker {
+    at_line_start: bool,
+    /// Where to record the lines for the phase summary, and which stream they're from.
+    phases: Option<(Arc<Mutex<Phases>>, usize)>,
+}
+
+impl LineTracker {
+    fn new(phases: Option<(Arc<Mutex<Phases>>, usize)>) -> LineTracker {
+        LineTracker {
+            at_line_start: true,
+            phases,
+        }
+    }
+
+    /// Copies `chunk` into `out`, inserting `prefix` at the start of each line.
+    fn process(&mut self, chunk: &[u8], elapsed: Duration, prefix: Option<&str>, out: &mut Vec<u8>) {
+        let mut phases = self.phases.as_ref().map(|(phases, stream)| (lock(phases), *stream));
+        for line in chunk.split_inclusive(|&b| b == b'\n') {
+            if self.at_line_start {
+                if let Some(prefix) = prefix {
+                    out.extend_from_slice(prefix.as_bytes());
+                }
+                if let Some((phases, stream)) = &mut phases {
+                    phases.start_line(*stream, elapsed);
+                }
+            }
+            if let Some((phases, stream)) = &mut phases {
+                phases.extend_line(*stream, line.strip_suffix(b"\n").unwrap_or(line));
+            }
+
+            out.extend_from_slice(line);
+            self.at_line_start = line.ends_with(b"\n");
+        }
+    }
+}
+
+const STDOUT: usize = 0;
+const STDERR: usize = 1;
+
+/// The largest gaps between lines written to either stream. Both readers share it, so rather than keeping every line
+/// until the command exits, only the lines either side of the largest gaps so far are kept.
+#[derive(Debug, Default)]
+struct Phases {
+    /// Numbers each line in the order they started.
+    lines: u64,
+    /// The line which started most recently on either stream, and when.
+    last: Option<(u64, Duration)>,
+    /// The line each stream is part way through, which more of its text may be read for.
+    current: [Option<u64>; 2],
+    /// Largest first, the length of each gap and the lines before and after it.
+    gaps: Vec<(Duration, Option<u64>, u64)>,
+    /// The start of each line which is still needed, without the trailing newline.
+    text: HashMap<u64, Vec<u8>>,
+}
+
+impl Phases {
+    fn start_line(&mut self, stream: usize, at: Duration) {
+        let line = self.lines;
+        self.lines += 1;
+        let (previous, duration) = match self.last {
+            Some((previous, last)) => (Some(previous), at.saturating_sub(last)),
+            None => (None, at),
+        };
+        // Reads are timed before taking the lock, so the other stream may have just started a slightly later line
+        if self.last.is_none_or(|(_, last)| at >= last) {
+            self.last = Some((line, at));
+        }
+        self.current[stream] = Some(line);
+        self.text.insert(line, vec![]);
+
+        // Ties go to the earlier gap
+        let i = self.gaps.partition_point(|&(d, _, _)| d >= duration);
+        if i < PHASE_SUMMARY_GAPS {
+            self.gaps.insert(i, (duration, previous, line));
+            self.gaps.truncate(PHASE_SUMMARY_GAPS);
+        }
+
+        let (last, current, gaps) = (self.last, self.current, &self.gaps);
+        self.text.retain(|&l, _| {
+            last.is_some_and(|(last, _)| last == l)
+                || current.contains(&Some(l))
+                || gaps.iter().any(|&(_, previous, next)| previous == Some(l) || next == l)
+        });
+    }
+
+    fn extend_line(&mut self, stream: usize, content: &[u8]) {
+        if let Some(text) = self.current[stream].and_then(|line| self.text.get_mut(&line)) {
+            let room = MAX_LINE_TEXT.saturating_sub(text.len());
+            text.extend_from_slice(&content[..room.min(content.len())]);
+        }
+    }
+
+    fn gaps(&self) -> Vec<Gap> {
+        let text = |line| String::from_utf8_lossy(&self.text[&line]).into_owned();
+        self.gaps
+            .iter()
+            .map(|&(duration, previous, next)| Gap {
+                duration,
+                previous: previous.map(text),
+                next: text(next),
+            })
+            .collect()
+    }
+}
+
+/// Locks the phases, even if the other reader panicked while holding them.
+fn lock(phases: &Mutex<Phases>) -> MutexGuard<'_, Phases> {
+    phases.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Continues a 64-bit FNV-1a hash with the given bytes.
+fn fnv1a(mut hash: u64, bytes: &[u8]) -> u64 {
+    for &b in bytes {
+        hash ^= b as u64;
+        hash = hash.wrapping_mul(FNV_PRIME);
+    }
+
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fnv1a() {
+        assert_eq!(fnv1a(FNV_OFFSET_BASIS, b""), 0xcbf29ce484222325);
+        assert_eq!(fnv1a(FNV_OFFSET_BASIS, b"a"), 0xaf63dc4c8601ec8c);
+        assert_eq!(fnv1a(FNV_OFFSET_BASIS, b"foobar"), 0x85944171f73967e8);
+        assert_eq!(fnv1a(fnv1a(FNV_OFFSET_BASIS, b"foo"), b"bar"), 0x85944171f73967e8);
+    }
+
+    #[test]
+    fn test_reader() {
+        let (read, write) = cloexec_pipe().unwrap();
+        // SAFETY: the write end of the pipe was just created and is only used here
+        let mut write = unsafe { File::from_raw_fd(write) };
+        write.write_all(b"one\ntwo\nthree").unwrap();
+        drop(write);
+
+        let phases = Arc::new(Mutex::new(Phases::default()));
+        let reader = Reader {
+            fd: read,
+            start: Instant::now(),
+            tee: None,
+            hash: Some(FNV_OFFSET_BASIS),
+            stamp: None,
+            tracker: LineTracker::new(Some((phases.clone(), STDOUT))),
+        };
+        let stream = reader.read().unwrap();
+        assert_eq!(stream.bytes, 13);
+        assert_eq!(stream.lines, 3);
+        assert!(stream.first_byte.is_some());
+        assert_eq!(stream.hash, Some(fnv1a(FNV_OFFSET_BASIS, b"one\ntwo\nthree")));
+        let mut lines = lock(&phases).gaps().into_iter().map(|g| g.next).collect::<Vec<_>>();
+        lines.sort();
+        assert_eq!(lines, vec!["one", "three", "two"]);
+    }
+
+    #[test]
+    fn test_line_tracker() {
+        let phases = Arc::new(Mutex::new(Phases::default()));
+        let mut tracker = LineTracker::new(Some((phases.clone(), STDOUT)));
+        let mut out = vec![];
+        tracker.process(b"a\nb", Duration::from_secs(1), Some("> "), &mut out);
+        tracker.process(b"c\n\nd", Duration::from_secs(3), Some("] "), &mut out);
+        assert_eq!(out, b"> a\n> bc\n] \n] d");
+
+        let gaps = lock(&phases)
+            .gaps()
+            .into_iter()
+            .map(|g| (g.duration.as_secs(), g.previous, g.next))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            gaps,
+            vec![
+                (2, Some("bc".into()), "".into()),
+                (1, None, "a".into()),
+                (0, Some("a".into()), "bc".into()),
+                (0, Some("".into()), "d".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_phases() {
+        let mut phases = Phases::default();
+        for (stream, at, text) in [
+            (STDOUT, 1, "load"),
+            (STDOUT, 2, "compute"),
+            (STDERR, 4, "warning"),
+            (STDOUT, 9, "write"),
+        ] {
+            phases.start_line(stream, Duration::from_secs(at));
+            phases.extend_line(stream, text.as_bytes());
+        }
+
+        let gaps = phases.gaps();
+        assert_eq!(
+            gaps[..2],
+            [
+                Gap {
+                    duration: Duration::from_secs(5),
+                    previous: Some("warning".into()),
+                    next: "write".into(),
+                },
+                Gap {
+                    duration: Duration::from_secs(2),
+                    previous: Some("compute".into()),
+                    next: "warning".into(),
+                },
+            ]
+        );
+        assert_eq!(gaps[2].previous, None);
+    }
+
+    #[test]
+    fn test_phases_bounded() {
+        let mut phases = Phases::default();
+        for i in 0..10_000 {
+            let stream = if i % 3 == 0 { STDERR } else { STDOUT };
+            phases.start_line(stream, Duration::from_millis(i * (i % 7)));
+            phases.extend_line(stream, format!("line {}", i).as_bytes());
+        }
+
+        // Only the lines either side of the gaps which are kept, the last line, and one being written on each stream
+        assert_eq!(phases.gaps.len(), PHASE_SUMMARY_GAPS);
+        assert!(phases.text.len() <= 2 * PHASE_SUMMARY_GAPS + 3, "{}", phases.text.len());
+        assert!(phases.gaps().iter().all(|g| g.next.starts_with("line ")));
+    }
+}
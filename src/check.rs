@@ -0,0 +1,211 @@
+use crate::ffi;
+use anyhow::Result;
+
+/// If the 1 minute load average per CPU is above this, other work is likely competing with the timed process.
+const HIGH_LOAD_PER_CPU: f64 = 0.5;
+
+type Check = fn() -> Result<Option<Finding>>;
+
+/// Something in the environment that's known to add noise to measurements.
+#[derive(Debug)]
+pub struct Finding {
+    pub name: &'static str,
+    pub message: String,
+    pub hint: String,
+}
+
+/// Inspects the system for settings that make timings less reproducible.
+///
+/// Checks which can't be performed (missing files, unsupported platforms, etc) are skipped.
+pub fn check_env() -> Vec<Finding> {
+    let checks: &[(&str, Check)] = &[
+        #[cfg(target_os = "linux")]
+        ("cpu_governor", linux::cpu_governor),
+        #[cfg(target_os = "linux")]
+        ("cpu_boost", linux::cpu_boost),
+        #[cfg(target_os = "linux")]
+        ("aslr", linux::aslr),
+        #[cfg(target_os = "linux")]
+        ("thp", linux::transparent_hugepages),
+        #[cfg(target_os = "linux")]
+        ("swap", linux::swap),
+        ("load", load),
+    ];
+
+    checks
+        .iter()
+        .filter_map(|(name, check)| match check() {
+            Ok(finding) => finding,
+            Err(e) => {
+                log::debug!("skipped {} check: {}", name, e);
+                None
+            }
+        })
+        .collect()
+}
+
+fn load() -> Result<Option<Finding>> {
+    let cpus = ffi::cpu_count()? as f64;
+    let load = ffi::load_average()?[0];
+    if load / cpus <= HIGH_LOAD_PER_CPU {
+        return Ok(None);
+    }
+
+    Ok(Some(Finding {
+        name: "load",
+        message: format!("1 minute load average is {:.2} with {} cpus", load, cpus),
+        hint: "stop other running programs, or wait for background jobs to finish".into(),
+    }))
+}
+
+/// Returns the value surrounded by brackets, which is how sysfs marks the active choice: `always [madvise] never`.
+fn selected_value(s: &str) -> Option<&str> {
+    let start = s.find('[')? + 1;
+    let end = start + s[start..].find(']')?;
+    Some(&s[start..end])
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use super::{selected_value, Finding};
+    use crate::ffi;
+    use anyhow::{anyhow, bail, Result};
+    use bytesize::ByteSize;
+    use std::fs;
+
+    pub fn cpu_governor() -> Result<Option<Finding>> {
+        let mut governors = vec![];
+        for entry in fs::read_dir("/sys/devices/system/cpu")? {
+            let name = entry?.file_name();
+            let name = name.to_string_lossy();
+            if !is_cpu_dir(&name) {
+                continue;
+            }
+
+            let path = format!("/sys/devices/system/cpu/{}/cpufreq/scaling_governor", name);
+            if let Ok(governor) = fs::read_to_string(path) {
+                governors.push((name.to_string(), governor.trim().to_string()));
+            }
+        }
+
+        if governors.is_empty() {
+            bail!("no cpufreq governors found");
+        }
+
+        let mut slow = governors
+            .into_iter()
+            .filter(|(_, governor)| governor != "performance")
+            .map(|(cpu, governor)| format!("{}={}", cpu, governor))
+            .collect::<Vec<_>>();
+        if slow.is_empty() {
+            return Ok(None);
+        }
+
+        slow.sort();
+        Ok(Some(Finding {
+            name: "cpu_governor",
+            message: format!("not using the performance governor: {}", slow.join(", ")),
+            hint: "sudo cpupower frequency-set --governor performance".into(),
+        }))
+    }
+
+    pub fn cpu_boost() -> Result<Option<Finding>> {
+        // intel_pstate exposes the inverse of the generic cpufreq boost switch
+        if let Ok(no_turbo) = fs::read_to_string("/sys/devices/system/cpu/intel_pstate/no_turbo") {
+            if no_turbo.trim() == "1" {
+                return Ok(None);
+            }
+
+            return Ok(Some(Finding {
+                name: "cpu_boost",
+                message: "turbo boost is enabled (intel_pstate)".into(),
+                hint: "echo 1 | sudo tee /sys/devices/system/cpu/intel_pstate/no_turbo".into(),
+            }));
+        }
+
+        let boost = fs::read_to_string("/sys/devices/system/cpu/cpufreq/boost")?;
+        if boost.trim() == "0" {
+            return Ok(None);
+        }
+
+        Ok(Some(Finding {
+            name: "cpu_boost",
+            message: "frequency boost is enabled (cpufreq)".into(),
+            hint: "echo 0 | sudo tee /sys/devices/system/cpu/cpufreq/boost".into(),
+        }))
+    }
+
+    pub fn aslr() -> Result<Option<Finding>> {
+        let value = fs::read_to_string("/proc/sys/kernel/randomize_va_space")?;
+        if value.trim() == "0" {
+            return Ok(None);
+        }
+
+        Ok(Some(Finding {
+            name: "aslr",
+            message: format!(
+                "address space layout randomisation is enabled (randomize_va_space={})",
+                value.trim()
+            ),
+            hint: "echo 0 | sudo tee /proc/sys/kernel/randomize_va_space".into(),
+        }))
+    }
+
+    pub fn transparent_hugepages() -> Result<Option<Finding>> {
+        let value = fs::read_to_string("/sys/kernel/mm/transparent_hugepage/enabled")?;
+        let mode = selected_value(&value).ok_or_else(|| anyhow!("unrecognised value: {}", value.trim()))?;
+        if mode != "always" {
+            return Ok(None);
+        }
+
+        Ok(Some(Finding {
+            name: "thp",
+            message: "transparent hugepages are always enabled, page fault and memory numbers will vary".into(),
+            hint: "echo madvise | sudo tee /sys/kernel/mm/transparent_hugepage/enabled".into(),
+        }))
+    }
+
+    pub fn swap() -> Result<Option<Finding>> {
+        let used = ffi::mem::swap_used()?;
+        if used == 0 {
+            return Ok(None);
+        }
+
+        Ok(Some(Finding {
+            name: "swap",
+            message: format!("{} of swap is in use", ByteSize(used).to_string_as(true)),
+            hint: "free up memory, then move pages back into memory with: sudo swapoff -a && sudo swapon -a".into(),
+        }))
+    }
+
+    fn is_cpu_dir(name: &str) -> bool {
+        name.strip_prefix("cpu")
+            .is_some_and(|n| !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()))
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_is_cpu_dir() {
+            assert!(is_cpu_dir("cpu0"));
+            assert!(is_cpu_dir("cpu12"));
+            assert!(!is_cpu_dir("cpu"));
+            assert!(!is_cpu_dir("cpufreq"));
+            assert!(!is_cpu_dir("cpuidle"));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_selected_value() {
+        assert_eq!(selected_value("always [madvise] never"), Some("madvise"));
+        assert_eq!(selected_value("[always] madvise never\n"), Some("always"));
+        assert_eq!(selected_value("always madvise never"), None);
+    }
+}
@@ -0,0 +1,628 @@
+//! Settings which are applied in the child process, between `fork` and `execve`.
+//! Since other threads may have held locks when we forked, everything called in the child is async-signal-safe: it
+//! doesn't allocate, and errors are returned as the `errno` of the call which failed.
+
+use crate::ffi::cvt;
+use crate::fmt::parse_suffixed;
+use anyhow::{anyhow, bail, Context, Result};
+use bytesize::ByteSize;
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use std::{env, fmt};
+
+/// How close the peak resident set size must come to an `as` or `data` limit for it to be blamed for a failure.
+const NEAR_LIMIT: f64 = 0.9;
+
+/// Settings used to reduce noise from the rest of the system.
+#[derive(Debug, Clone, Default)]
+pub struct Isolation {
+    /// Disable address space layout randomisation.
+    pub no_aslr: bool,
+    /// Pin the process to these CPUs.
+    pub cpus: Option<Vec<usize>>,
+    /// Set the niceness of the process.
+    pub nice: Option<i32>,
+    /// Run with the `SCHED_IDLE` scheduling policy.
+    pub sched_idle: bool,
+}
+
+impl Isolation {
+    /// Returns a description of each setting that's in effect.
+    pub(crate) fn describe(&self) -> Vec<String> {
+        let mut settings = vec![];
+        if self.no_aslr {
+            settings.push("no_aslr".into());
+        }
+        if let Some(cpus) = &self.cpus {
+            let cpus = cpus.iter().map(|c| c.to_string()).collect::<Vec<_>>();
+            settings.push(format!("cpus={}", cpus.join(",")));
+        }
+        if let Some(nice) = self.nice {
+            settings.push(format!("nice={}", nice));
+        }
+        if self.sched_idle {
+            settings.push("sched_idle".into());
+        }
+
+        settings
+    }
+
+    /// Applies the settings to the current process, these are all inherited across `execve`. Called in the child.
+    pub(crate) fn apply(&self) -> io::Result<()> {
+        if self.no_aslr {
+            disable_aslr()?;
+        }
+        if let Some(cpus) = &self.cpus {
+            set_affinity(cpus)?;
+        }
+        if let Some(nice) = self.nice {
+            // SAFETY: only modifies the scheduling priority of the calling process
+            cvt(unsafe { libc::setpriority(libc::PRIO_PROCESS as _, 0, nice) })?;
+        }
+        if self.sched_idle {
+            set_sched_idle()?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A resource which can be limited with `setrlimit`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resource {
+    AddressSpace,
+    Data,
+    Cpu,
+    OpenFiles,
+    Processes,
+    Core,
+    Stack,
+}
+
+impl Resource {
+    fn name(self) -> &'static str {
+        match self {
+            Self::AddressSpace => "as",
+            Self::Data => "data",
+            Self::Cpu => "cpu",
+            Self::OpenFiles => "nofile",
+            Self::Processes => "nproc",
+            Self::Core => "core",
+            Self::Stack => "stack",
+        }
+    }
+
+    fn rlimit(self) -> libc::c_int {
+        (match self {
+            Self::AddressSpace => libc::RLIMIT_AS,
+            Self::Data => libc::RLIMIT_DATA,
+            Self::Cpu => libc::RLIMIT_CPU,
+            Self::OpenFiles => libc::RLIMIT_NOFILE,
+            Self::Processes => libc::RLIMIT_NPROC,
+            Self::Core => libc::RLIMIT_CORE,
+            Self::Stack => libc::RLIMIT_STACK,
+        }) as libc::c_int
+    }
+}
+
+/// A resource limit to set on the child, e.g.: `as=2G` or `cpu=30s`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limit {
+    pub resource: Resource,
+    /// The limit in the unit `setrlimit` expects (bytes, seconds or a count), `None` is unlimited.
+    pub value: Option<u64>,
+}
+
+impl Limit {
+    pub fn parse(s: &str) -> Result<Limit> {
+        let (name, value) = s
+            .split_once('=')
+            .ok_or_else(|| anyhow!("Invalid limit '{}', expected RESOURCE=VALUE", s))?;
+        let resource = match name {
+            "as" => Resource::AddressSpace,
+            "data" => Resource::Data,
+            "cpu" => Resource::Cpu,
+            "nofile" => Resource::OpenFiles,
+            "nproc" => Resource::Processes,
+            "core" => Resource::Core,
+            "stack" => Resource::Stack,
+            _ => bail!(
+                "Unrecognised resource '{}' in limit '{}' [possible values: as, data, cpu, nofile, nproc, core, stack]",
+                name,
+                s
+            ),
+        };
+
+        let value = match value {
+            "unlimited" => None,
+            value => Some(
+                match resource {
+                    Resource::AddressSpace | Resource::Data | Resource::Core | Resource::Stack => {
+                        parse_suffixed(value, &[("K", 1 << 10), ("M", 1 << 20), ("G", 1 << 30), ("T", 1 << 40)])
+                    }
+                    Resource::Cpu => parse_suffixed(value, &[("s", 1), ("m", 60), ("h", 60 * 60)]),
+                    Resource::OpenFiles | Resource::Processes => parse_suffixed(value, &[]),
+                }
+                .with_context(|| format!("Invalid value in limit '{}'", s))?,
+            ),
+        };
+
+        Ok(Limit { resource, value })
+    }
+
+    /// Applies the limit to the current process, limits are inherited across `execve`. Called in the child.
+    pub(crate) fn apply(&self) -> io::Result<()> {
+        let value = self.value.map_or(libc::RLIM_INFINITY, |v| v as libc::rlim_t);
+        let mut limit = libc::rlimit {
+            rlim_cur: value,
+            rlim_max: value,
+        };
+        // Leave a second between the soft and hard limits, so the process receives SIGXCPU before SIGKILL
+        if self.resource == Resource::Cpu && value != libc::RLIM_INFINITY {
+            limit.rlim_max = value + 1;
+        }
+
+        // SAFETY: `limit` is a valid pointer for the duration of the call
+        cvt(unsafe { libc::setrlimit(self.resource.rlimit() as _, &limit) })?;
+        Ok(())
+    }
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.resource, self.value) {
+            (resource, None) => write!(f, "{}=unlimited", resource.name()),
+            (Resource::Cpu, Some(secs)) => write!(f, "cpu={}s", secs),
+            (Resource::OpenFiles | Resource::Processes, Some(n)) => write!(f, "{}={}", self.resource.name(), n),
+            (resource, Some(bytes)) => write!(f, "{}={}", resource.name(), ByteSize(bytes).to_string_as(true)),
+        }
+    }
+}
+
+/// Explains how the limits set on the child may have caused how it terminated.
+pub fn explain_limits(limits: &[Limit], status: i32, cpu_time: Duration, max_rss: u64) -> Vec<String> {
+    let limit_of = |resource| limits.iter().find(|l| l.resource == resource && l.value.is_some());
+    let signal = if libc::WIFSIGNALED(status) {
+        Some(libc::WTERMSIG(status))
+    } else {
+        None
+    };
+    let mut reasons = vec![];
+
+    if let Some(signal) = signal {
+        if let Some(limit) = limit_of(Resource::Cpu) {
+            let exceeded = limit.value.is_some_and(|secs| cpu_time.as_secs() >= secs);
+            if signal == libc::SIGXCPU {
+                reasons.push(format!("RLIMIT_CPU ({}) was exceeded, which caused SIGXCPU", limit));
+            } else if signal == libc::SIGKILL && exceeded {
+                reasons.push(format!("RLIMIT_CPU ({}) was exceeded, which caused SIGKILL", limit));
+            }
+        }
+        if let Some(limit) = limit_of(Resource::Stack) {
+            if signal == libc::SIGSEGV {
+                reasons.push(format!(
+                    "SIGSEGV was likely a stack overflow caused by RLIMIT_STACK ({})",
+                    limit
+                ));
+            }
+        }
+    }
+
+    // A failed allocation usually ends in an abort, a segfault dereferencing it or the OOM killer, otherwise only
+    // blame the limit if the command came close to it (its resident set is at most its address space)
+    let crashed = matches!(signal, Some(libc::SIGABRT | libc::SIGSEGV | libc::SIGKILL));
+    let failed = signal.is_some() || (libc::WIFEXITED(status) && libc::WEXITSTATUS(status) != 0);
+    if failed && reasons.is_empty() {
+        for resource in [Resource::AddressSpace, Resource::Data] {
+            if let Some(limit) = limit_of(resource) {
+                let near = limit
+                    .value
+                    .is_some_and(|bytes| max_rss as f64 >= bytes as f64 * NEAR_LIMIT);
+                if crashed || near {
+                    reasons.push(format!(
+                        "possibly an allocation failure caused by RLIMIT_{} ({})",
+                        resource.name().to_uppercase(),
+                        limit
+                    ));
+                }
+            }
+        }
+    }
+
+    reasons
+}
+
+/// The environment the child is run with.
+#[derive(Debug, Clone, Default)]
+pub struct Environment {
+    /// Start from an empty environment rather than inheriting ours.
+    pub clear: bool,
+    /// Variables to set, in the order they were given.
+    pub vars: Vec<(OsString, OsString)>,
+}
+
+impl Environment {
+    /// Parses `KEY=VAL` and adds it to the variables to set.
+    pub fn add(&mut self, s: &str) -> Result<()> {
+        let (key, value) = parse_var(s)?;
+        self.set(key, value);
+        Ok(())
+    }
+
+    /// Adds a variable to set, later values of the same variable replace earlier ones.
+    pub fn set(&mut self, key: impl Into<OsString>, value: impl Into<OsString>) {
+        self.vars.push((key.into(), value.into()));
+    }
+
+    /// Reads `KEY=VAL` lines from a file, skipping empty lines and lines starting with `#`.
+    pub fn add_file(&mut self, path: &Path) -> Result<()> {
+        let contents = fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        for line in contents.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            self.add(line)
+                .with_context(|| format!("Invalid line in {}", path.display()))?;
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn describe(&self) -> String {
+        let base = if self.clear { "cleared" } else { "inherited" };
+        let vars = self
+            .vars
+            .iter()
+            .map(|(k, v)| format!("{}={}", k.to_string_lossy(), v.to_string_lossy()));
+        std::iter::once(base.to_string())
+            .chain(vars)
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// The variables the child is run with, starting from ours unless the environment is cleared.
+    pub(crate) fn resolve(&self) -> Vec<(OsString, OsString)> {
+        let mut vars = if self.clear {
+            vec![]
+        } else {
+            env::vars_os().collect::<Vec<_>>()
+        };
+        for (key, value) in &self.vars {
+            vars.retain(|(k, _)| k != key);
+            vars.push((key.clone(), value.clone()));
+        }
+
+        vars
+    }
+}
+
+/// Where one of the child's standard streams is connected to.
+#[derive(Debug, Clone)]
+pub enum Redirect {
+    Null,
+    File(PathBuf),
+}
+
+impl Redirect {
+    pub fn parse(s: OsString) -> Redirect {
+        if s == "null" {
+            Redirect::Null
+        } else {
+            Redirect::File(s.into())
+        }
+    }
+
+    fn path(&self) -> &Path {
+        match self {
+            Redirect::Null => Path::new("/dev/null"),
+            Redirect::File(path) => path,
+        }
+    }
+}
+
+impl fmt::Display for Redirect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Redirect::Null => f.write_str("null"),
+            Redirect::File(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+/// Redirections for the child's standard streams, `None` inherits ours.
+#[derive(Debug, Clone, Default)]
+pub struct Stdio {
+    pub stdin: Option<Redirect>,
+    pub stdout: Option<Redirect>,
+    pub stderr: Option<Redirect>,
+}
+
+impl Stdio {
+    /// Opens the files the streams are redirected to, so they can be shared by several processes.
+    pub(crate) fn open(&self) -> Result<Opened> {
+        let stdin = match &self.stdin {
+            Some(stdin) => Some(File::open(stdin.path()).with_context(|| format!("Failed to open stdin: {}", stdin))?),
+            None => None,
+        };
+        let create = |redirection: &Option<Redirect>| -> Result<Option<File>> {
+            match redirection {
+                Some(redirection) => Ok(Some(
+                    OpenOptions::new()
+                        .write(true)
+                        .create(true)
+                        .truncate(true)
+                        .open(redirection.path())
+                        .with_context(|| format!("Failed to open {}", redirection))?,
+                )),
+                None => Ok(None),
+            }
+        };
+
+        let stdout = create(&self.stdout)?;
+        let mut stderr = create(&self.stderr)?;
+        // Both streams share one file description if they're redirected to the same file, otherwise each would have
+        // its own offset and they'd overwrite each other
+        if let (Some(out), Some(err)) = (&stdout, &stderr) {
+            if same_file(out, err)? {
+                stderr = Some(out.try_clone().context("Failed to share stdout with stderr")?);
+            }
+        }
+
+        Ok(Opened { stdin, stdout, stderr })
+    }
+}
+
+/// The files of a `Stdio` once they've been opened.
+#[derive(Debug)]
+pub struct Opened {
+    stdin: Option<File>,
+    stdout: Option<File>,
+    stderr: Option<File>,
+}
+
+impl Opened {
+    /// Redirects our standard streams, `stdin` and `stdout` are left alone if they're false, e.g.: for a stage in the
+    /// middle of a pipeline. Called in the child.
+    pub fn apply(&self, stdin: bool, stdout: bool) -> io::Result<()> {
+        for (file, fd, wanted) in [
+            (&self.stdin, libc::STDIN_FILENO, stdin),
+            (&self.stdout, libc::STDOUT_FILENO, stdout),
+            (&self.stderr, libc::STDERR_FILENO, true),
+        ] {
+            if let (Some(file), true) = (file, wanted) {
+                redirect(file, fd)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Whether two open files are the same file, even if they were opened through different paths.
+fn same_file(a: &File, b: &File) -> Result<bool> {
+    use std::os::unix::fs::MetadataExt;
+
+    let (a, b) = (a.metadata()?, b.metadata()?);
+    Ok((a.dev(), a.ino()) == (b.dev(), b.ino()))
+}
+
+/// Replaces `fd` with a duplicate of `file`, the original is closed when `file` is dropped or on `execve`.
+fn redirect(file: &File, fd: RawFd) -> io::Result<()> {
+    // SAFETY: dup2 only operates on file descriptors
+    cvt(unsafe { libc::dup2(file.as_raw_fd(), fd) })?;
+    Ok(())
+}
+
+fn parse_var(s: &str) -> Result<(&str, &str)> {
+    match s.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key, value)),
+        _ => bail!("Invalid environment variable '{}', expected KEY=VAL", s),
+    }
+}
+
+/// Parses a list of CPUs in the same format as `taskset --cpu-list`, e.g.: `0,2,4-7`.
+pub fn parse_cpu_list(s: &str) -> Result<Vec<usize>> {
+    let parse = |n: &str| {
+        n.trim()
+            .parse::<usize>()
+            .map_err(|e| anyhow!("Invalid cpu '{}' in cpu list '{}': {}", n, s, e))
+    };
+
+    let mut cpus = vec![];
+    for part in s.split(',') {
+        match part.split_once('-') {
+            Some((start, end)) => {
+                let (start, end) = (parse(start)?, parse(end)?);
+                if start > end {
+                    bail!("Invalid cpu range '{}' in cpu list '{}'", part, s);
+                }
+                cpus.extend(start..=end);
+            }
+            None => cpus.push(parse(part)?),
+        }
+    }
+
+    cpus.sort_unstable();
+    cpus.dedup();
+    Ok(cpus)
+}
+
+#[cfg(target_os = "linux")]
+fn disable_aslr() -> io::Result<()> {
+    // SAFETY: 0xffffffff reads the current persona without changing it
+    let persona = cvt(unsafe { libc::personality(0xffffffff) })?;
+    // SAFETY: only changes how this process is exec'd
+    cvt(unsafe { libc::personality((persona | libc::ADDR_NO_RANDOMIZE) as libc::c_ulong) })?;
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn set_affinity(cpus: &[usize]) -> io::Result<()> {
+    // SAFETY: an all-zero cpu_set_t is an empty set
+    let mut set: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+    for &cpu in cpus {
+        if cpu >= libc::CPU_SETSIZE as usize {
+            return Err(io::Error::from_raw_os_error(libc::EINVAL));
+        }
+        // SAFETY: the cpu was checked to be within the set
+        unsafe { libc::CPU_SET(cpu, &mut set) };
+    }
+
+    // SAFETY: `set` is a valid pointer of the given size for the duration of the call
+    cvt(unsafe { libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) })?;
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn set_sched_idle() -> io::Result<()> {
+    let param = libc::sched_param { sched_priority: 0 };
+    // SAFETY: `param` is a valid pointer for the duration of the call
+    cvt(unsafe { libc::sched_setscheduler(0, libc::SCHED_IDLE, &param) })?;
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn disable_aslr() -> io::Result<()> {
+    Err(io::Error::from_raw_os_error(libc::ENOSYS))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_affinity(_: &[usize]) -> io::Result<()> {
+    Err(io::Error::from_raw_os_error(libc::ENOSYS))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_sched_idle() -> io::Result<()> {
+    Err(io::Error::from_raw_os_error(libc::ENOSYS))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_cpu_list() {
+        assert_eq!(parse_cpu_list("0").unwrap(), vec![0]);
+        assert_eq!(parse_cpu_list("2,3").unwrap(), vec![2, 3]);
+        assert_eq!(parse_cpu_list("0,4-6,2").unwrap(), vec![0, 2, 4, 5, 6]);
+        assert_eq!(parse_cpu_list("1,1-2").unwrap(), vec![1, 2]);
+        assert!(parse_cpu_list("").is_err());
+        assert!(parse_cpu_list("3-1").is_err());
+        assert!(parse_cpu_list("a").is_err());
+    }
+
+    #[test]
+    fn test_limit_parse() {
+        let limit = |resource, value| Limit { resource, value };
+        assert_eq!(
+            Limit::parse("as=2G").unwrap(),
+            limit(Resource::AddressSpace, Some(2 << 30))
+        );
+        assert_eq!(
+            Limit::parse("data=512m").unwrap(),
+            limit(Resource::Data, Some(512 << 20))
+        );
+        assert_eq!(Limit::parse("stack=8192").unwrap(), limit(Resource::Stack, Some(8192)));
+        assert_eq!(Limit::parse("cpu=30s").unwrap(), limit(Resource::Cpu, Some(30)));
+        assert_eq!(Limit::parse("cpu=2m").unwrap(), limit(Resource::Cpu, Some(120)));
+        assert_eq!(Limit::parse("nofile=64").unwrap(), limit(Resource::OpenFiles, Some(64)));
+        assert_eq!(Limit::parse("core=unlimited").unwrap(), limit(Resource::Core, None));
+        assert!(Limit::parse("as").is_err());
+        assert!(Limit::parse("rss=1G").is_err());
+        assert!(Limit::parse("nproc=10K").is_err());
+        assert!(Limit::parse("cpu=-1").is_err());
+    }
+
+    #[test]
+    fn test_limit_display() {
+        assert_eq!(Limit::parse("as=2G").unwrap().to_string(), "as=2.0 GiB");
+        assert_eq!(Limit::parse("cpu=1h").unwrap().to_string(), "cpu=3600s");
+        assert_eq!(Limit::parse("nproc=10").unwrap().to_string(), "nproc=10");
+        assert_eq!(Limit::parse("stack=unlimited").unwrap().to_string(), "stack=unlimited");
+    }
+
+    #[test]
+    fn test_explain_limits() {
+        let limits = [Limit::parse("cpu=1s").unwrap(), Limit::parse("as=1G").unwrap()];
+        let signaled = |signal| signal & 0x7f;
+        let exited = |code: i32| (code & 0xff) << 8;
+        let (second, gib) = (Duration::from_secs(1), 1 << 30);
+        let none = Vec::<String>::new();
+
+        let reasons = explain_limits(&limits, signaled(libc::SIGXCPU), second, 0);
+        assert_eq!(reasons, vec!["RLIMIT_CPU (cpu=1s) was exceeded, which caused SIGXCPU"]);
+        let reasons = explain_limits(&limits, signaled(libc::SIGKILL), second * 2, 0);
+        assert_eq!(reasons, vec!["RLIMIT_CPU (cpu=1s) was exceeded, which caused SIGKILL"]);
+        let reasons = explain_limits(&limits, signaled(libc::SIGABRT), Duration::ZERO, 0);
+        assert_eq!(
+            reasons,
+            vec!["possibly an allocation failure caused by RLIMIT_AS (as=1.0 GiB)"]
+        );
+        let reasons = explain_limits(&limits, exited(1), Duration::ZERO, gib - 1024);
+        assert_eq!(
+            reasons,
+            vec!["possibly an allocation failure caused by RLIMIT_AS (as=1.0 GiB)"]
+        );
+        assert_eq!(explain_limits(&limits, exited(1), Duration::ZERO, gib / 10), none);
+        assert_eq!(
+            explain_limits(&limits, signaled(libc::SIGTERM), Duration::ZERO, 0),
+            none
+        );
+        assert_eq!(explain_limits(&limits, exited(0), Duration::ZERO, gib), none);
+        assert_eq!(explain_limits(&[], exited(1), Duration::ZERO, gib), none);
+    }
+
+    #[test]
+    fn test_stdio_same_file() {
+        let path = std::env::temp_dir().join(format!("timers-test-stdio-{}", std::process::id()));
+        crate::Command::new("sh")
+            .args(["-c", "echo one; echo two >&2; echo three"])
+            .stdout(Redirect::File(path.clone()))
+            .stderr(Redirect::File(path.clone()))
+            .measure()
+            .unwrap();
+        let written = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(written, "one\ntwo\nthree\n");
+    }
+
+    #[test]
+    fn test_parse_var() {
+        assert_eq!(parse_var("KEY=VAL").unwrap(), ("KEY", "VAL"));
+        assert_eq!(parse_var("KEY=").unwrap(), ("KEY", ""));
+        assert_eq!(parse_var("KEY=a=b").unwrap(), ("KEY", "a=b"));
+        assert!(parse_var("KEY").is_err());
+        assert!(parse_var("=VAL").is_err());
+    }
+
+    #[test]
+    fn test_environment_describe() {
+        let mut environment = Environment::default();
+        assert_eq!(environment.describe(), "inherited");
+        environment.clear = true;
+        environment.add("FOO=bar").unwrap();
+        environment.add("BAZ=1").unwrap();
+        assert_eq!(environment.describe(), "cleared, FOO=bar, BAZ=1");
+    }
+
+    #[test]
+    fn test_isolation_describe() {
+        assert!(Isolation::default().describe().is_empty());
+        let isolation = Isolation {
+            no_aslr: true,
+            cpus: Some(vec![2, 3]),
+            nice: Some(10),
+            sched_idle: true,
+        };
+        assert_eq!(
+            isolation.describe(),
+            vec!["no_aslr", "cpus=2,3", "nice=10", "sched_idle"]
+        );
+    }
+}
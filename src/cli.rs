@@ -1,6 +1,14 @@
+use crate::capture;
+use crate::child::{self, Environment, Isolation, Limit, Redirect, Stdio};
+use crate::fmt::TimeFormat;
+use crate::sample;
+use crate::status::SignalExit;
+use crate::Command;
 use anyhow::{bail, Result};
 use lexopt::Parser;
-use std::{env, ffi::OsString, process};
+use std::{env, ffi::OsString, path::PathBuf, process, time::Duration};
+
+const DEFAULT_SAMPLE_INTERVAL: Duration = Duration::from_millis(10);
 
 fn print_help() {
     println!(
@@ -14,20 +22,103 @@ Project home page: {crate_homepage}
 
 USAGE:
     {bin} [OPTIONS] [--] <COMMAND_LINE>...
+    {bin} pipe [OPTIONS] [--] <COMMAND_LINE> ::: <COMMAND_LINE>...
+    {bin} --pid <PID> [OPTIONS]
 
 OPTIONS:
     -t, --time <TIME_FORMAT>    [possible values: normal, seconds, milli, micro, nano]
+        --check-env             Check the system for settings that make timings less reproducible
+                                (the command line is optional when this is given)
+        --no-aslr               Disable address space layout randomisation for the command
+        --cpus <CPU_LIST>       Pin the command to the given cpus, e.g.: 2,3 or 0-3
+        --nice <N>              Run the command with the given niceness
+        --sched-idle            Run the command with the SCHED_IDLE scheduling policy
+        --limit <RESOURCE=VALUE>
+                                Set a resource limit on the command, may be given multiple times
+                                [possible resources: as, data, cpu, nofile, nproc, core, stack]
+        --env <KEY=VAL>         Set an environment variable for the command, may be given multiple times
+        --env-clear             Run the command with an empty environment (before applying --env)
+        --env-file <FILE>       Set environment variables from a file of KEY=VAL lines
+        --chdir <DIR>           Run the command in the given directory
+        --stdin <FILE|null>     Read the command's stdin from the given file
+        --stdout <FILE|null>    Write the command's stdout to the given file
+        --stderr <FILE|null>    Write the command's stderr to the given file
+        --capture               Pipe the command's stdout and stderr through timers and report on them
+        --tee                   Capture the command's output, and also write it to the terminal
+        --hash-output           Capture the command's output, and report a hash of each stream
+        --timestamp-lines       Write the command's output to the terminal, prefixing each line with the
+                                time since the command was started
+        --phase-summary         Capture the command's output, and report the largest gaps between lines
+        --markers               Pass a file descriptor to the command in $TIMERS_MARKER_FD, each line
+                                written to it is recorded as a lap
+        --marker-cpu            Record the command's cpu times with each lap (implies --markers)
+        --tree                  Follow every process the command starts, and report the time and memory
+                                used by each of them (uses ptrace, or polls /proc if that's not allowed)
+        --syscalls              Trace the command's system calls with ptrace, and report how many times each
+                                was called, how many failed and the time spent in them (requires Linux 5.3)
+        --files                 Report the files the command opened, stat'ed or executed, and the bytes read
+                                from and written to each of them (uses ptrace, or samples /proc/<pid>/fd if
+                                that's not allowed, in which case bytes aren't known)
+        --sample-interval <DURATION>
+                                Sample the command's memory, cpu usage, threads, open files and disk io at
+                                this interval while it runs, e.g.: 10ms or 1s
+        --sample-output <FILE>  Write the samples to the given file, as JSON if it ends with .json and CSV
+                                otherwise (samples every {default_interval}ms unless --sample-interval is given)
+        --watch-live            Show a full-screen view of the command's processes and threads while it runs,
+                                with their cpu, memory and disk usage (anything the command writes to the
+                                terminal is overwritten by the view and lost, use --stdout or --capture)
+        --export-trace <FILE>   Write the run, the processes it started, laps and samples to a Chrome Trace
+                                Event file, which can be opened in ui.perfetto.dev
+        --export-html <FILE>    Write a self-contained HTML report of the run, with charts of the processes
+                                it started, laps and samples, and details of the system it was run on
+        --jobs <N>              Run N copies of the command at once, and report on each of them along with
+                                the total wall time and throughput
+        --scale <N,...>         Run the command at each of the given levels of concurrency, e.g.: 1,2,4,8,
+                                and report the speedup and efficiency of each compared to the first
+        --untraced-run          Before tracing the command, run it once without tracing and report its times
+                                separately, since tracing slows it down
+        --signal-exit-convention <raw|128+n>
+                                If the command is killed by a signal, exit with the signal's number (raw, the
+                                default) or 128 plus it like a shell does, so it can't be mistaken for an exit code
+        --exclude-stopped       Don't count time the command spent stopped (e.g.: suspended with Ctrl-Z) in its
+                                real time, it's reported separately as stopped_time either way
+        --pid <PID>             Attach to a process which is already running instead of starting a command, and
+                                report on it once it exits (only what can be read from /proc is available, since
+                                we aren't its parent, and requires Linux 5.3)
     -h, --help                  Print help information
     -V, --version               Print version information
 
+SUBCOMMANDS:
+    pipe                        Run a pipeline of commands separated by ':::', connecting each one's stdout to
+                                the next one's stdin, and report the times and memory used by each stage
+                                (--stdin is read by the first stage, and --stdout written by the last)
+
 EXAMPLES:
     {bin} -- cat some/file
     {bin} --time nano -- sh -c 'echo "do something"'
+    {bin} --check-env
+    {bin} --no-aslr --cpus 2,3 -- ./some-benchmark
+    {bin} --limit as=2G --limit cpu=30s -- ./some-program
+    {bin} --env-clear --env PATH=/usr/bin --stdout null -- ./some-program
+    {bin} --tee --hash-output -- ./some-program
+    {bin} --timestamp-lines --phase-summary -- make
+    {bin} --markers -- sh -c 'sleep 1; echo setup >&$TIMERS_MARKER_FD; sleep 2'
+    {bin} --tree -- make -j4
+    {bin} --syscalls --untraced-run -- ./some-program
+    {bin} --files -- cargo build
+    {bin} --sample-interval 10ms --sample-output timeline.csv -- ./some-program
+    {bin} --watch-live --stdout build.log -- make -j4
+    {bin} --scale 1,2,4,8 --stdout null -- ./some-service-benchmark
+    {bin} --tree --markers --sample-interval 10ms --export-trace trace.json -- make -j4
+    {bin} --sample-interval 10ms --export-html report.html -- ./some-benchmark
+    {bin} --pid 1234 --sample-interval 1s --sample-output server.csv
+    {bin} pipe --stdin some/file -- sort ::: uniq -c ::: gzip -9
 
     "#,
-            bin = env!("CARGO_BIN_NAME"),
+            bin = env!("CARGO_PKG_NAME"),
             crate_name = env!("CARGO_PKG_NAME"),
             crate_version = env!("CARGO_PKG_VERSION"),
+            default_interval = DEFAULT_SAMPLE_INTERVAL.as_millis(),
             crate_homepage = env!("CARGO_PKG_HOMEPAGE"),
             crate_authors = env!("CARGO_PKG_AUTHORS").split(':').collect::<Vec<_>>().join("\n"),
         )
@@ -35,49 +126,34 @@ EXAMPLES:
     );
 }
 
-#[derive(Debug, Clone, Copy)]
-pub enum TimeFormat {
-    Normal,
-    Seconds,
-    Milli,
-    Micro,
-    Nano,
-}
-
-impl ToString for TimeFormat {
-    fn to_string(&self) -> String {
-        match self {
-            Self::Normal => "normal".into(),
-            Self::Seconds => "seconds".into(),
-            Self::Milli => "milli".into(),
-            Self::Micro => "micro".into(),
-            Self::Nano => "nano".into(),
-        }
-    }
-}
-
-impl From<String> for TimeFormat {
-    fn from(value: String) -> Self {
-        match value.as_str() {
-            "normal" => Self::Normal,
-            "seconds" => Self::Seconds,
-            "milli" => Self::Milli,
-            "micro" => Self::Micro,
-            "nano" => Self::Nano,
-            _ => {
-                eprintln!(
-                    "Unrecognised time format: '{value}', defaulting to {}",
-                    Self::Normal.to_string()
-                );
-                Self::Normal
-            }
-        }
-    }
-}
-
 #[derive(Debug)]
 pub struct Args {
     pub time_format: Option<TimeFormat>,
+    pub check_env: bool,
+    pub isolation: Isolation,
+    pub limits: Vec<Limit>,
+    pub environment: Environment,
+    pub chdir: Option<PathBuf>,
+    pub stdio: Stdio,
+    pub capture: Option<capture::Options>,
+    /// Whether to pass a marker fd to the child, and if so whether to record its cpu times with each lap.
+    pub markers: Option<bool>,
+    pub tree: bool,
+    pub syscalls: bool,
+    pub files: bool,
+    pub sample: Option<sample::Options>,
+    pub watch_live: bool,
+    pub export_trace: Option<PathBuf>,
+    pub export_html: Option<PathBuf>,
+    pub untraced_run: bool,
+    pub exclude_stopped: bool,
+    pub signal_exit: SignalExit,
+    pub jobs: Option<u32>,
+    pub scale: Option<Vec<u32>>,
+    /// A running process to attach to, instead of running a command.
+    pub pid: Option<libc::pid_t>,
+    /// The command of each stage, when run as `pipe`.
+    pub pipeline: Option<Vec<Vec<OsString>>>,
     // TODO: JSON output
     pub args: Vec<OsString>,
 }
@@ -87,14 +163,100 @@ impl Args {
         use lexopt::prelude::*;
 
         let mut time_format = None;
+        let mut check_env = false;
+        let mut isolation = Isolation::default();
+        let mut limits = vec![];
+        let mut environment = Environment::default();
+        let mut chdir = None;
+        let mut stdio = Stdio::default();
+        let mut capture: Option<capture::Options> = None;
+        let mut markers = None;
+        let mut tree = false;
+        let mut syscalls = false;
+        let mut files = false;
+        let mut sample_interval = None;
+        let mut sample_output = None;
+        let mut watch_live = false;
+        let mut export_trace = None;
+        let mut export_html = None;
+        let mut untraced_run = false;
+        let mut exclude_stopped = false;
+        let mut signal_exit = SignalExit::default();
+        let mut jobs = None;
+        let mut scale = None;
+        let mut pid = None;
         let mut command_line = vec![];
 
-        let mut parser = Parser::from_env();
+        // `pipe` is only a subcommand in front of everything else, so `timers -- pipe` still runs a command called pipe
+        let mut raw = env::args_os().skip(1).peekable();
+        let pipe = raw.next_if(|arg| arg == "pipe").is_some();
+        let mut parser = Parser::from_args(raw);
         while let Some(arg) = parser.next()? {
             match arg {
                 Short('t') | Long("time") if command_line.is_empty() => {
-                    time_format = Some(parser.value()?.string()?.into())
+                    // An unknown format isn't fatal, the times are still worth reporting
+                    let value = parser.value()?.string()?;
+                    time_format = Some(value.parse().unwrap_or_else(|e| {
+                        eprintln!("{}, defaulting to {}", e, TimeFormat::Normal);
+                        TimeFormat::Normal
+                    }))
+                }
+                Long("check-env") if command_line.is_empty() => check_env = true,
+                Long("no-aslr") if command_line.is_empty() => isolation.no_aslr = true,
+                Long("cpus") if command_line.is_empty() => {
+                    isolation.cpus = Some(child::parse_cpu_list(&parser.value()?.string()?)?)
+                }
+                Long("nice") if command_line.is_empty() => isolation.nice = Some(parser.value()?.parse()?),
+                Long("sched-idle") if command_line.is_empty() => isolation.sched_idle = true,
+                Long("limit") if command_line.is_empty() => limits.push(Limit::parse(&parser.value()?.string()?)?),
+                Long("env") if command_line.is_empty() => environment.add(&parser.value()?.string()?)?,
+                Long("env-clear") if command_line.is_empty() => environment.clear = true,
+                Long("env-file") if command_line.is_empty() => environment.add_file(&PathBuf::from(parser.value()?))?,
+                Long("chdir") if command_line.is_empty() => chdir = Some(PathBuf::from(parser.value()?)),
+                Long("stdin") if command_line.is_empty() => stdio.stdin = Some(Redirect::parse(parser.value()?)),
+                Long("stdout") if command_line.is_empty() => stdio.stdout = Some(Redirect::parse(parser.value()?)),
+                Long("stderr") if command_line.is_empty() => stdio.stderr = Some(Redirect::parse(parser.value()?)),
+                Long("capture") if command_line.is_empty() => {
+                    capture.get_or_insert_with(Default::default);
+                }
+                Long("tee") if command_line.is_empty() => capture.get_or_insert_with(Default::default).tee = true,
+                Long("hash-output") if command_line.is_empty() => {
+                    capture.get_or_insert_with(Default::default).hash = true
+                }
+                Long("timestamp-lines") if command_line.is_empty() => {
+                    let capture = capture.get_or_insert_with(Default::default);
+                    capture.tee = true;
+                    capture.timestamp_lines = true;
+                }
+                Long("phase-summary") if command_line.is_empty() => {
+                    capture.get_or_insert_with(Default::default).phase_summary = true
                 }
+                Long("markers") if command_line.is_empty() => {
+                    markers.get_or_insert(false);
+                }
+                Long("marker-cpu") if command_line.is_empty() => markers = Some(true),
+                Long("tree") if command_line.is_empty() => tree = true,
+                Long("syscalls") if command_line.is_empty() => syscalls = true,
+                Long("files") if command_line.is_empty() => files = true,
+                Long("sample-interval") if command_line.is_empty() => {
+                    sample_interval = Some(sample::parse_interval(&parser.value()?.string()?)?)
+                }
+                Long("sample-output") if command_line.is_empty() => {
+                    sample_output = Some(PathBuf::from(parser.value()?))
+                }
+                Long("watch-live") if command_line.is_empty() => watch_live = true,
+                Long("export-trace") if command_line.is_empty() => export_trace = Some(PathBuf::from(parser.value()?)),
+                Long("export-html") if command_line.is_empty() => export_html = Some(PathBuf::from(parser.value()?)),
+                Long("untraced-run") if command_line.is_empty() => untraced_run = true,
+                Long("exclude-stopped") if command_line.is_empty() => exclude_stopped = true,
+                Long("signal-exit-convention") if command_line.is_empty() => {
+                    signal_exit = SignalExit::parse(&parser.value()?.string()?)?
+                }
+                Long("jobs") if command_line.is_empty() => jobs = Some(parser.value()?.parse()?),
+                Long("scale") if command_line.is_empty() => {
+                    scale = Some(crate::jobs::parse_scale(&parser.value()?.string()?)?)
+                }
+                Long("pid") if command_line.is_empty() => pid = Some(parser.value()?.parse()?),
                 Short('h') | Long("help") if command_line.is_empty() => {
                     print_help();
                     process::exit(0);
@@ -115,13 +277,121 @@ impl Args {
             }
         }
 
-        if command_line.is_empty() {
+        if command_line.is_empty() && !check_env && pid.is_none() {
             bail!("no command given");
         }
+        if capture.is_some() && (stdio.stdout.is_some() || stdio.stderr.is_some()) {
+            bail!("--stdout and --stderr can't be used when capturing output");
+        }
+        if jobs == Some(0) {
+            bail!("--jobs must be at least 1");
+        }
+        if jobs.is_some() && scale.is_some() {
+            bail!("--jobs and --scale can't be used together");
+        }
+        // Options which only make sense when the command is run once, on its own
+        let single_run = capture.is_some()
+            || markers.is_some()
+            || tree
+            || syscalls
+            || files
+            || sample_interval.is_some()
+            || sample_output.is_some()
+            || watch_live
+            || export_trace.is_some()
+            || export_html.is_some()
+            || untraced_run;
+        if (jobs.is_some() || scale.is_some()) && single_run {
+            bail!("--jobs and --scale can only be combined with options which set up the command");
+        }
+        if pipe && (single_run || jobs.is_some() || scale.is_some()) {
+            bail!("pipe can only be combined with options which set up the command");
+        }
+        if pid.is_some() && !command_line.is_empty() {
+            bail!("--pid can't be used with a command line");
+        }
+        if pid.is_some()
+            && (pipe
+                || jobs.is_some()
+                || scale.is_some()
+                || !isolation.describe().is_empty()
+                || !limits.is_empty()
+                || environment.clear
+                || !environment.vars.is_empty()
+                || chdir.is_some()
+                || stdio.stdin.is_some()
+                || stdio.stdout.is_some()
+                || stdio.stderr.is_some()
+                || capture.is_some()
+                || markers.is_some()
+                || tree
+                || syscalls
+                || files
+                || export_trace.is_some()
+                || export_html.is_some()
+                || untraced_run
+                || exclude_stopped
+                || signal_exit != SignalExit::default())
+        {
+            bail!(
+                "--pid can only be combined with --time, --check-env, --sample-interval, --sample-output and \
+                 --watch-live"
+            );
+        }
+        if untraced_run && !(tree || syscalls || files) {
+            bail!("--untraced-run requires --tree, --syscalls or --files");
+        }
 
         Ok(Args {
             time_format,
+            check_env,
+            isolation,
+            limits,
+            environment,
+            chdir,
+            stdio,
+            capture,
+            markers,
+            tree,
+            syscalls,
+            files,
+            sample: (sample_interval.is_some() || sample_output.is_some()).then(|| sample::Options {
+                interval: sample_interval.unwrap_or(DEFAULT_SAMPLE_INTERVAL),
+                output: sample_output,
+            }),
+            watch_live,
+            export_trace,
+            export_html,
+            untraced_run,
+            exclude_stopped,
+            signal_exit,
+            jobs,
+            scale,
+            pid,
+            pipeline: if pipe {
+                Some(crate::pipeline::split(&command_line)?)
+            } else {
+                None
+            },
             args: command_line,
         })
     }
+
+    /// The command to run with `argv`, set up as given on the command line.
+    pub fn command(&self, argv: &[OsString]) -> Command {
+        let mut command = Command::default();
+        command
+            .args(argv)
+            .environment(self.environment.clone())
+            .stdio(self.stdio.clone())
+            .isolation(self.isolation.clone());
+        if let Some(dir) = &self.chdir {
+            command.current_dir(dir);
+        }
+        for limit in &self.limits {
+            command.limit(*limit);
+        }
+
+        command
+    }
 }
@@ -0,0 +1,127 @@
+//! Reading several of the system's clocks at the start and end of a run, to notice when the system was suspended or
+//! its clock was adjusted in between. `Instant` uses `CLOCK_MONOTONIC`, which doesn't count time spent suspended.
+
+use crate::ffi;
+use std::time::Duration;
+
+/// Clocks which should agree to within this are assumed to have done so.
+const TOLERANCE: Duration = Duration::from_millis(10);
+/// Adjustments to the rate of the monotonic clock are only reported above this many parts per million.
+const SLEW_PPM: f64 = 200.0;
+
+/// The clocks at one point in time, those which couldn't be read are `None`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Reading {
+    /// Time since the Unix epoch, which may be set or stepped at any time.
+    pub realtime: Option<Duration>,
+    /// Like `Instant`, adjusted by NTP to run at the right rate but stopped while the system is suspended.
+    pub monotonic: Option<Duration>,
+    /// The monotonic clock without any adjustment to its rate.
+    pub monotonic_raw: Option<Duration>,
+    /// The monotonic clock including time spent suspended.
+    pub boottime: Option<Duration>,
+}
+
+impl Reading {
+    pub fn now() -> Reading {
+        let read = |clock| ffi::clock_gettime(clock).ok();
+        Reading {
+            realtime: read(libc::CLOCK_REALTIME),
+            monotonic: read(libc::CLOCK_MONOTONIC),
+            #[cfg(any(target_os = "linux", target_os = "macos"))]
+            monotonic_raw: read(libc::CLOCK_MONOTONIC_RAW),
+            #[cfg(not(any(target_os = "linux", target_os = "macos")))]
+            monotonic_raw: None,
+            #[cfg(target_os = "linux")]
+            boottime: read(libc::CLOCK_BOOTTIME),
+            #[cfg(not(target_os = "linux"))]
+            boottime: None,
+        }
+    }
+}
+
+/// A way in which the clocks disagreed over a run.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Drift {
+    /// The system was suspended for this long, which isn't included in real time.
+    Suspended(Duration),
+    /// The wall clock was set or stepped, by this many seconds.
+    Stepped(f64),
+    /// NTP was adjusting the rate of the monotonic clock, by this many parts per million.
+    Slewed(f64),
+}
+
+/// Compares how much time each clock says has passed between the readings.
+pub fn compare(start: &Reading, end: &Reading) -> Vec<Drift> {
+    let elapsed = |f: fn(&Reading) -> Option<Duration>| Some(f(end)?.as_secs_f64() - f(start)?.as_secs_f64());
+    let monotonic = elapsed(|r| r.monotonic);
+    let boottime = elapsed(|r| r.boottime);
+    let realtime = elapsed(|r| r.realtime);
+    let raw = elapsed(|r| r.monotonic_raw);
+    let tolerance = TOLERANCE.as_secs_f64();
+
+    let mut drifts = vec![];
+    if let (Some(monotonic), Some(boottime)) = (monotonic, boottime) {
+        if boottime - monotonic > tolerance {
+            drifts.push(Drift::Suspended(Duration::from_secs_f64(boottime - monotonic)));
+        }
+    }
+    // The wall clock keeps counting while suspended, so it's compared with the clock which does too
+    if let (Some(realtime), Some(reference)) = (realtime, boottime.or(monotonic)) {
+        if (realtime - reference).abs() > tolerance {
+            drifts.push(Drift::Stepped(realtime - reference));
+        }
+    }
+    if let (Some(monotonic), Some(raw)) = (monotonic, raw) {
+        let ppm = 1e6 * (monotonic - raw) / raw;
+        if (monotonic - raw).abs() > tolerance / 10.0 && ppm.abs() > SLEW_PPM {
+            drifts.push(Drift::Slewed(ppm));
+        }
+    }
+
+    drifts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reading(realtime: u64, monotonic: u64, monotonic_raw: u64, boottime: u64) -> Reading {
+        Reading {
+            realtime: Some(Duration::from_millis(realtime)),
+            monotonic: Some(Duration::from_millis(monotonic)),
+            monotonic_raw: Some(Duration::from_millis(monotonic_raw)),
+            boottime: Some(Duration::from_millis(boottime)),
+        }
+    }
+
+    #[test]
+    fn test_compare() {
+        let start = reading(10_000_000, 1_000, 1_000, 1_000);
+        assert_eq!(compare(&start, &reading(10_005_000, 6_000, 6_000, 6_000)), vec![]);
+        assert_eq!(compare(&start, &start), vec![]);
+        assert_eq!(compare(&Reading::default(), &Reading::default()), vec![]);
+
+        // Suspended for 60s, which the wall clock counted too
+        assert_eq!(
+            compare(&start, &reading(10_065_000, 6_000, 6_000, 66_000)),
+            vec![Drift::Suspended(Duration::from_secs(60))]
+        );
+        // The wall clock was set back an hour
+        assert_eq!(
+            compare(&start, &reading(10_005_000 - 3_600_000, 6_000, 6_000, 6_000)),
+            vec![Drift::Stepped(-3600.0)]
+        );
+        match &compare(&start, &reading(10_005_000, 6_000, 5_995, 6_000))[..] {
+            [Drift::Slewed(ppm)] => assert!((ppm - 1000.0).abs() < 2.0),
+            drifts => panic!("unexpected drifts: {:?}", drifts),
+        }
+    }
+
+    #[test]
+    fn test_now() {
+        let now = Reading::now();
+        assert!(now.realtime.is_some());
+        assert!(now.monotonic.is_some());
+    }
+}
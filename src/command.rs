@@ -0,0 +1,425 @@
+//! Building the command to be measured and starting it, much like `std::process::Command` but we fork it ourselves so
+//! we're the one to wait for it and get its resource usage.
+
+use crate::child::{Environment, Isolation, Limit, Opened, Redirect, Stdio};
+use crate::clocks;
+use crate::ffi::{self, cvt};
+use crate::measurement::Measurement;
+use anyhow::{anyhow, Context, Result};
+use nix::unistd::{fork, ForkResult};
+use std::ffi::{CString, OsStr, OsString};
+use std::fs::File;
+use std::io::{self, Read};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::io::{AsRawFd, FromRawFd};
+use std::path::{Path, PathBuf};
+use std::ptr;
+use std::time::Instant;
+
+/// A command to run, along with how its process is set up before it's exec'd.
+#[derive(Debug, Clone, Default)]
+pub struct Command {
+    argv: Vec<OsString>,
+    environment: Environment,
+    chdir: Option<PathBuf>,
+    stdio: Stdio,
+    limits: Vec<Limit>,
+    isolation: Isolation,
+}
+
+impl Command {
+    pub fn new(program: impl Into<OsString>) -> Command {
+        Command {
+            argv: vec![program.into()],
+            ..Default::default()
+        }
+    }
+
+    pub fn arg(&mut self, arg: impl Into<OsString>) -> &mut Command {
+        self.argv.push(arg.into());
+        self
+    }
+
+    pub fn args<I: IntoIterator<Item = S>, S: Into<OsString>>(&mut self, args: I) -> &mut Command {
+        self.argv.extend(args.into_iter().map(Into::into));
+        self
+    }
+
+    pub fn env(&mut self, key: impl Into<OsString>, value: impl Into<OsString>) -> &mut Command {
+        self.environment.set(key, value);
+        self
+    }
+
+    /// Starts from an empty environment rather than inheriting ours, variables set with `env` are still set.
+    pub fn env_clear(&mut self) -> &mut Command {
+        self.environment.clear = true;
+        self
+    }
+
+    pub fn environment(&mut self, environment: Environment) -> &mut Command {
+        self.environment = environment;
+        self
+    }
+
+    /// The directory to run in, a relative path is relative to the directory we're in when the command is started.
+    pub fn current_dir(&mut self, dir: impl Into<PathBuf>) -> &mut Command {
+        self.chdir = Some(dir.into());
+        self
+    }
+
+    pub fn stdin(&mut self, redirect: Redirect) -> &mut Command {
+        self.stdio.stdin = Some(redirect);
+        self
+    }
+
+    pub fn stdout(&mut self, redirect: Redirect) -> &mut Command {
+        self.stdio.stdout = Some(redirect);
+        self
+    }
+
+    pub fn stderr(&mut self, redirect: Redirect) -> &mut Command {
+        self.stdio.stderr = Some(redirect);
+        self
+    }
+
+    pub fn stdio(&mut self, stdio: Stdio) -> &mut Command {
+        self.stdio = stdio;
+        self
+    }
+
+    pub fn limit(&mut self, limit: Limit) -> &mut Command {
+        self.limits.push(limit);
+        self
+    }
+
+    pub fn isolation(&mut self, isolation: Isolation) -> &mut Command {
+        self.isolation = isolation;
+        self
+    }
+
+    pub fn get_argv(&self) -> &[OsString] {
+        &self.argv
+    }
+
+    pub fn get_environment(&self) -> &Environment {
+        &self.environment
+    }
+
+    pub fn get_current_dir(&self) -> Option<&Path> {
+        self.chdir.as_deref()
+    }
+
+    pub fn get_stdio(&self) -> &Stdio {
+        &self.stdio
+    }
+
+    pub fn get_limits(&self) -> &[Limit] {
+        &self.limits
+    }
+
+    pub fn get_isolation(&self) -> &Isolation {
+        &self.isolation
+    }
+
+    /// Runs the command to completion and returns what was measured of it.
+    pub fn measure(&self) -> Result<Measurement> {
+        self.spawn()?.wait()
+    }
+
+    /// Starts the command, returning once it has been exec'd or an error if it couldn't be.
+    pub fn spawn(&self) -> Result<Child> {
+        let mut child = self.spawn_with(|| Ok(()))?;
+        child.started()?;
+        Ok(child)
+    }
+
+    /// Starts the command, calling `setup` in the forked process after its redirections are applied and before it
+    /// changes directory. Unlike `spawn` this returns straight away, in case `setup` waits for us, and errors in the
+    /// forked process are returned by `Child::started`, `Child::wait` or `Child::finish`.
+    ///
+    /// Other threads may have held locks when we forked, so `setup` must only make async-signal-safe calls: it
+    /// mustn't allocate, take locks or log. Errors should be made with `io::Error::last_os_error`.
+    pub(crate) fn spawn_with(&self, setup: impl FnOnce() -> io::Result<()>) -> Result<Child> {
+        // Everything the child needs is prepared beforehand, so it only has to make system calls
+        let program = self.argv.first().ok_or_else(|| anyhow!("No command given"))?;
+        let argv = self.argv.iter().map(|a| c_string(a)).collect::<Result<Vec<_>>>()?;
+        let vars = self.environment.resolve();
+        let envp = vars
+            .iter()
+            .map(|(key, value)| c_string(&[key.as_os_str(), value.as_os_str()].join(OsStr::new("="))))
+            .collect::<Result<Vec<_>>>()?;
+        let path = vars
+            .iter()
+            .find(|(key, _)| key == "PATH")
+            .map(|(_, value)| value.as_os_str());
+        let programs = candidates(program, path)?;
+        // The null-terminated arrays of pointers execve takes, which point into `argv` and `envp`
+        let argv_ptrs = argv
+            .iter()
+            .map(|a| a.as_ptr())
+            .chain(Some(ptr::null()))
+            .collect::<Vec<_>>();
+        let envp_ptrs = envp
+            .iter()
+            .map(|v| v.as_ptr())
+            .chain(Some(ptr::null()))
+            .collect::<Vec<_>>();
+        let chdir = self.chdir.as_ref().map(|dir| c_string(dir.as_os_str())).transpose()?;
+        // Redirections are opened here, so their paths are relative to where we were run from
+        let stdio = self.stdio.open()?;
+        let (errors, report) = ffi::cloexec_pipe()?;
+        // SAFETY: we own both ends of the new pipe
+        let (errors, report) = unsafe { (File::from_raw_fd(errors), File::from_raw_fd(report)) };
+
+        let started = clocks::Reading::now();
+        // NOTE: REAL_TIMER START: immediately before forking the process
+        let start = Instant::now();
+        match unsafe { fork() }.context("Failed to fork")? {
+            ForkResult::Parent { child } => Ok(Child {
+                pid: child.as_raw(),
+                start,
+                started,
+                command: self.clone(),
+                errors: Some(errors),
+            }),
+            ForkResult::Child => {
+                let (step, e) = self.exec(&stdio, setup, chdir.as_ref(), &programs, &argv_ptrs, &envp_ptrs);
+                // Tell the parent which step failed and why, the pipe is closed if the exec succeeds
+                let mut failure = [0; 8];
+                failure[..4].copy_from_slice(&step.to_ne_bytes());
+                failure[4..].copy_from_slice(&e.raw_os_error().unwrap_or(0).to_ne_bytes());
+                // SAFETY: write and _exit are async-signal-safe, and the buffer outlives the call
+                unsafe {
+                    libc::write(
+                        report.as_raw_fd(),
+                        failure.as_ptr() as *const libc::c_void,
+                        failure.len(),
+                    );
+                    libc::_exit(127)
+                }
+            }
+        }
+    }
+
+    /// Prepares the forked process and replaces it with the command, only returning if that failed. Called in the
+    /// child, so everything it calls is async-signal-safe.
+    fn exec(
+        &self,
+        stdio: &Opened,
+        setup: impl FnOnce() -> io::Result<()>,
+        chdir: Option<&CString>,
+        programs: &[CString],
+        argv: &[*const libc::c_char],
+        envp: &[*const libc::c_char],
+    ) -> (u32, io::Error) {
+        let step = |step: u32| move |e| (step, e);
+        let prepared = (|| {
+            stdio.apply(true, true).map_err(step(Step::REDIRECT))?;
+            setup().map_err(step(Step::SETUP))?;
+            if let Some(dir) = chdir {
+                // SAFETY: the path is a valid C string
+                cvt(unsafe { libc::chdir(dir.as_ptr()) }).map_err(step(Step::CHDIR))?;
+            }
+            self.isolation.apply().map_err(step(Step::ISOLATION))?;
+            for (i, limit) in self.limits.iter().enumerate() {
+                limit.apply().map_err(step(Step::LIMIT + i as u32))?;
+            }
+            Ok(())
+        })();
+        if let Err(failure) = prepared {
+            return failure;
+        }
+
+        // Like execvp, try each directory in PATH, and prefer reporting that one wasn't executable
+        let mut denied = false;
+        let mut error = io::Error::from_raw_os_error(libc::ENOENT);
+        for program in programs {
+            // SAFETY: the arrays are null-terminated and point to C strings which outlive the call
+            unsafe { libc::execve(program.as_ptr(), argv.as_ptr(), envp.as_ptr()) };
+            error = io::Error::last_os_error();
+            match error.raw_os_error() {
+                Some(libc::EACCES) => denied = true,
+                Some(libc::ENOENT) | Some(libc::ENOTDIR) | Some(libc::ESTALE) | Some(libc::ENODEV) => {}
+                _ => return (Step::EXEC, error),
+            }
+        }
+
+        if denied {
+            error = io::Error::from_raw_os_error(libc::EACCES);
+        }
+        (Step::EXEC, error)
+    }
+}
+
+/// Which step of setting up the child failed, sent back to the parent along with the `errno`.
+struct Step;
+
+impl Step {
+    const REDIRECT: u32 = 0;
+    const SETUP: u32 = 1;
+    const CHDIR: u32 = 2;
+    const ISOLATION: u32 = 3;
+    const EXEC: u32 = 4;
+    /// Followed by the index of the limit.
+    const LIMIT: u32 = 5;
+}
+
+fn c_string(s: &OsStr) -> Result<CString> {
+    CString::new(s.as_bytes()).with_context(|| format!("Invalid argument, it contains a nul byte: {:?}", s))
+}
+
+/// The paths to try to exec the program at, searching `PATH` like `execvp` unless it contains a slash.
+fn candidates(program: &OsStr, path: Option<&OsStr>) -> Result<Vec<CString>> {
+    if program.as_bytes().contains(&b'/') {
+        return Ok(vec![c_string(program)?]);
+    }
+
+    let path = path.map_or(&b"/bin:/usr/bin"[..], |p| p.as_bytes());
+    path.split(|&b| b == b':')
+        .map(|dir| {
+            // An empty directory is the current one
+            let dir = Path::new(if dir.is_empty() {
+                OsStr::new(".")
+            } else {
+                OsStr::from_bytes(dir)
+            });
+            c_string(dir.join(program).as_os_str())
+        })
+        .collect()
+}
+
+/// A command which has been started.
+#[derive(Debug)]
+pub struct Child {
+    pid: libc::pid_t,
+    start: Instant,
+    started: clocks::Reading,
+    command: Command,
+    /// Closed by the command when it's exec'd, or written to if it couldn't be.
+    errors: Option<File>,
+}
+
+impl Child {
+    pub fn pid(&self) -> libc::pid_t {
+        self.pid
+    }
+
+    /// When the command was started, everything else which watches it measures time from here.
+    pub fn start(&self) -> Instant {
+        self.start
+    }
+
+    /// Waits until the command has been exec'd. If it couldn't be, the process is reaped and the reason returned.
+    pub(crate) fn started(&mut self) -> Result<()> {
+        match self.failure() {
+            Some(e) => {
+                let _ = ffi::wait_for_pid(self.pid);
+                Err(e)
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Waits for the command to terminate.
+    pub fn wait(mut self) -> Result<Measurement> {
+        self.started()?;
+        let waited = ffi::wait_for_pid(self.pid)?;
+        self.finish(waited)
+    }
+
+    /// Completes the measurement of a command which has already been waited for elsewhere, e.g.: by a
+    /// `trace::Tracker`. Real time ends when the wait saw it terminate.
+    pub(crate) fn finish(mut self, waited: ffi::Waited) -> Result<Measurement> {
+        let real = waited.ended.saturating_duration_since(self.start);
+        let ended = clocks::Reading::now();
+        if let Some(e) = self.failure() {
+            return Err(e);
+        }
+
+        Ok(Measurement::new(self.pid, real, waited, self.started, ended))
+    }
+
+    /// Blocks until the command has been exec'd or failed to be, returning why it failed.
+    fn failure(&mut self) -> Option<anyhow::Error> {
+        let mut failure = vec![];
+        if let Err(e) = self.errors.take()?.read_to_end(&mut failure) {
+            return Some(anyhow::Error::new(e).context("Failed to read whether the command started"));
+        }
+        if failure.len() != 8 {
+            return None;
+        }
+
+        let step = u32::from_ne_bytes([failure[0], failure[1], failure[2], failure[3]]);
+        let errno = i32::from_ne_bytes([failure[4], failure[5], failure[6], failure[7]]);
+        let command = &self.command;
+        let context = match step {
+            Step::REDIRECT => "Failed to redirect the command's standard streams".to_string(),
+            Step::SETUP => "Failed to set up the command".to_string(),
+            Step::CHDIR => format!(
+                "Failed to change directory to {}",
+                command.chdir.as_deref().unwrap_or_else(|| Path::new("")).display()
+            ),
+            Step::ISOLATION => format!("Failed to apply isolation: {}", command.isolation.describe().join(", ")),
+            Step::EXEC => format!("Failed to execute {}", command.argv[0].to_string_lossy()),
+            limit => format!("Failed to set limit {}", command.limits[(limit - Step::LIMIT) as usize]),
+        };
+
+        Some(anyhow::Error::new(io::Error::from_raw_os_error(errno)).context(context))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::status::Status;
+
+    #[test]
+    fn test_measure() {
+        let measurement = Command::new("sh")
+            .args(["-c", "test \"$TIMERS_TEST\" = yes && test \"$(pwd)\" = / && exit 3"])
+            .env("TIMERS_TEST", "yes")
+            .current_dir("/")
+            .measure()
+            .unwrap();
+        assert_eq!(measurement.exit(), Some(Status::Exited(3)));
+        assert!(measurement.real > measurement.stopped.time);
+        assert!(measurement.max_rss > 0);
+
+        assert!(Command::default().measure().is_err());
+    }
+
+    #[test]
+    fn test_spawn_failure() {
+        let e = Command::new("/nonexistent/cmd").spawn().unwrap_err();
+        assert_eq!(
+            format!("{:#}", e),
+            "Failed to execute /nonexistent/cmd: No such file or directory (os error 2)"
+        );
+
+        let e = Command::new("true").current_dir("/nonexistent").measure().unwrap_err();
+        assert!(format!("{:#}", e).starts_with("Failed to change directory to /nonexistent: "));
+
+        // Errors in the forked process are only seen once it's let go of
+        let mut child = Command::new("true")
+            .spawn_with(|| Err(io::Error::from_raw_os_error(libc::EPERM)))
+            .unwrap();
+        assert!(format!("{:#}", child.started().unwrap_err()).starts_with("Failed to set up the command: "));
+    }
+
+    #[test]
+    fn test_candidates() {
+        let paths = |program: &str, path: Option<&str>| {
+            candidates(OsStr::new(program), path.map(OsStr::new))
+                .unwrap()
+                .into_iter()
+                .map(|c| c.into_string().unwrap())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(paths("./run", Some("/bin")), vec!["./run"]);
+        assert_eq!(
+            paths("ls", Some("/usr/bin::/bin")),
+            vec!["/usr/bin/ls", "./ls", "/bin/ls"]
+        );
+        assert_eq!(paths("ls", None), vec!["/bin/ls", "/usr/bin/ls"]);
+    }
+}
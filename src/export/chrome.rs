@@ -0,0 +1,173 @@
+//! The Chrome Trace Event format, which can be opened in Perfetto or `chrome://tracing`. See
+//! https://docs.google.com/document/d/1CvAClvFfyA5R-PhYUmn5OOQtYMH4h6I0nSsKchNAySU for details.
+
+use super::Run;
+use crate::fmt::json_string;
+use anyhow::{Context, Result};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::time::Duration;
+
+/// Writes the run as a trace: a span for the command and each process it started, an instant for each lap, and
+/// counter tracks for sampled memory and cpu usage.
+pub fn write(path: &Path, run: &Run) -> Result<()> {
+    let file = File::create(path).with_context(|| format!("Failed to create {}", path.display()))?;
+    let mut out = BufWriter::new(file);
+    write_events(&mut out, &events(run))?;
+    out.flush()
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Returns each event as a JSON object.
+fn events(run: &Run) -> Vec<String> {
+    let mut events = vec![];
+    let pid = run.pid;
+    let command = run.argv.join(" ");
+
+    events.push(format!(
+        r#"{{"name": "process_name", "ph": "M", "pid": {}, "args": {{"name": {}}}}}"#,
+        pid,
+        json_string(&command)
+    ));
+    events.push(format!(
+        r#"{{"name": {}, "cat": "run", "ph": "X", "ts": {}, "dur": {}, "pid": {}, "tid": {}, "args": {{"user_us": {}, "sys_us": {}}}}}"#,
+        json_string(&command),
+        micros(Duration::ZERO),
+        micros(run.real),
+        pid,
+        pid,
+        micros(run.user),
+        micros(run.sys)
+    ));
+
+    // Each process is shown as a thread of the command, so they're grouped together
+    for process in run.tree.map_or(&[][..], |t| &t.processes[..]) {
+        if process.pid == pid {
+            continue;
+        }
+        let name = if process.argv.is_empty() {
+            process.pid.to_string()
+        } else {
+            process.argv.join(" ")
+        };
+        events.push(format!(
+            r#"{{"name": "thread_name", "ph": "M", "pid": {}, "tid": {}, "args": {{"name": {}}}}}"#,
+            pid,
+            process.pid,
+            json_string(&format!("{} ({})", name, process.pid))
+        ));
+        events.push(format!(
+            r#"{{"name": {}, "cat": "process", "ph": "X", "ts": {}, "dur": {}, "pid": {}, "tid": {}, "args": {{"parent": {}, "user_us": {}, "sys_us": {}, "max_rss_bytes": {}}}}}"#,
+            json_string(&name),
+            micros(process.start),
+            micros(process.end.unwrap_or(run.real).saturating_sub(process.start)),
+            pid,
+            process.pid,
+            process.parent.map_or("null".into(), |p| p.to_string()),
+            micros(process.user),
+            micros(process.sys),
+            process.max_rss.map_or("null".into(), |b| b.to_string())
+        ));
+    }
+
+    for lap in run.laps.unwrap_or_default() {
+        events.push(format!(
+            r#"{{"name": {}, "cat": "lap", "ph": "i", "s": "p", "ts": {}, "pid": {}, "tid": {}}}"#,
+            json_string(&lap.name),
+            micros(lap.at),
+            pid,
+            pid
+        ));
+    }
+
+    for sample in run.samples.unwrap_or_default() {
+        events.push(format!(
+            r#"{{"name": "rss", "ph": "C", "ts": {}, "pid": {}, "args": {{"bytes": {}}}}}"#,
+            micros(sample.at),
+            pid,
+            sample.rss
+        ));
+        events.push(format!(
+            r#"{{"name": "cpu", "ph": "C", "ts": {}, "pid": {}, "args": {{"percent": {:.2}}}}}"#,
+            micros(sample.at),
+            pid,
+            sample.cpu_pct
+        ));
+    }
+
+    events
+}
+
+fn write_events(out: &mut impl Write, events: &[String]) -> Result<()> {
+    writeln!(out, r#"{{"displayTimeUnit": "ms", "traceEvents": ["#)?;
+    for (i, event) in events.iter().enumerate() {
+        writeln!(out, "  {}{}", event, if i + 1 < events.len() { "," } else { "" })?;
+    }
+    writeln!(out, "]}}")?;
+
+    Ok(())
+}
+
+/// Timestamps and durations are in microseconds.
+fn micros(d: Duration) -> String {
+    format!("{:.3}", d.as_nanos() as f64 / 1_000.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::marker::Lap;
+    use crate::trace::{Method, Process, Tree};
+
+    #[test]
+    fn test_events() {
+        let tree = Tree {
+            method: Method::Ptrace,
+            processes: vec![Process {
+                pid: 43,
+                parent: Some(42),
+                argv: vec!["cc".into(), "-c".into()],
+                start: Duration::from_millis(1),
+                end: None,
+                user: Duration::ZERO,
+                sys: Duration::ZERO,
+                max_rss: None,
+            }],
+        };
+        let laps = [Lap {
+            name: "link".into(),
+            at: Duration::from_micros(1500),
+            cpu: None,
+        }];
+        let run = Run {
+            argv: vec!["make".into()],
+            pid: 42,
+            status: 0,
+            real: Duration::from_millis(2),
+            user: Duration::ZERO,
+            sys: Duration::ZERO,
+            tree: Some(&tree),
+            laps: Some(&laps),
+            samples: None,
+        };
+
+        let events = events(&run);
+        assert_eq!(events.len(), 5);
+        assert!(events[1].contains(r#""name": "make", "cat": "run", "ph": "X", "ts": 0.000, "dur": 2000.000"#));
+        assert!(events[2].contains(r#""tid": 43, "args": {"name": "cc -c (43)"}"#));
+        // Processes which outlived the command end with it
+        assert!(events[3].contains(r#""ts": 1000.000, "dur": 1000.000"#));
+        assert!(events[4].contains(r#""name": "link", "cat": "lap", "ph": "i", "s": "p", "ts": 1500.000"#));
+
+        let mut out = vec![];
+        write_events(&mut out, &events[..1]).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            format!(
+                "{{\"displayTimeUnit\": \"ms\", \"traceEvents\": [\n  {}\n]}}\n",
+                events[0]
+            )
+        );
+    }
+}
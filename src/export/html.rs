@@ -0,0 +1,280 @@
+//! A single HTML file with the results of a run, which can be shared and opened without a network connection.
+
+use super::Run;
+use crate::status::{self, Status};
+use anyhow::{Context, Result};
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+const CHART_WIDTH: f64 = 800.0;
+const CHART_HEIGHT: f64 = 200.0;
+/// Space around the plot for axis labels.
+const MARGIN_LEFT: f64 = 80.0;
+const MARGIN_BOTTOM: f64 = 24.0;
+const MARGIN_TOP: f64 = 12.0;
+/// Height of each row in the process timeline.
+const ROW_HEIGHT: f64 = 18.0;
+
+const STYLE: &str =
+    "body{font-family:sans-serif;margin:2em;color:#222}table{border-collapse:collapse;margin-bottom:1.5em}\
+td,th{padding:2px 12px;text-align:left;border-bottom:1px solid #ddd}\
+code{background:#f4f4f4;padding:2px 4px}svg{display:block;margin-bottom:1.5em}svg text{font-size:11px;fill:#555}";
+
+/// Writes the report, `fingerprint` describes the system it was run on.
+pub fn write(path: &Path, run: &Run, fingerprint: &[(&str, String)]) -> Result<()> {
+    fs::write(path, render(run, fingerprint)).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+fn render(run: &Run, fingerprint: &[(&str, String)]) -> String {
+    let mut html = String::new();
+    let command = run.argv.join(" ");
+    let _ = write!(
+        html,
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>{} - {}</title><style>{}</style></head><body>\n",
+        env!("CARGO_PKG_NAME"),
+        escape(&command),
+        STYLE
+    );
+    let _ = writeln!(
+        html,
+        "<h1>{}</h1>\n<p><code>{}</code></p>",
+        env!("CARGO_PKG_NAME"),
+        escape(&command)
+    );
+
+    let status = match Status::decode(run.status) {
+        Some(Status::Exited(code)) => format!("exited with {}", code),
+        Some(Status::Signaled { signal, core_dumped }) => format!(
+            "killed by {}{}",
+            status::describe_signal(signal),
+            if core_dumped { ", dumped core" } else { "" }
+        ),
+        _ => "-".into(),
+    };
+    html.push_str("<h2>Result</h2>\n");
+    html.push_str(&table(&[
+        ("status", status),
+        ("real", ms(run.real)),
+        ("user", ms(run.user)),
+        ("sys", ms(run.sys)),
+    ]));
+    html.push_str(&bars(&[("real", run.real), ("user", run.user), ("sys", run.sys)]));
+
+    if run.tree.is_some_and(|t| !t.processes.is_empty()) || run.laps.is_some_and(|l| !l.is_empty()) {
+        html.push_str("<h2>Timeline</h2>\n");
+        html.push_str(&timeline(run));
+    }
+
+    if let Some(samples) = run.samples.filter(|s| !s.is_empty()) {
+        let rss = samples
+            .iter()
+            .map(|s| (s.at, s.rss as f64 / (1 << 20) as f64))
+            .collect::<Vec<_>>();
+        let cpu = samples.iter().map(|s| (s.at, s.cpu_pct)).collect::<Vec<_>>();
+        html.push_str("<h2>Memory</h2>\n");
+        html.push_str(&line_chart(&rss, run.real, "MiB"));
+        html.push_str("<h2>CPU</h2>\n");
+        html.push_str(&line_chart(&cpu, run.real, "%"));
+    }
+
+    html.push_str("<h2>Environment</h2>\n");
+    html.push_str(&table(fingerprint));
+    html.push_str("</body></html>\n");
+    html
+}
+
+fn table(rows: &[(&str, String)]) -> String {
+    let mut html = String::from("<table>\n");
+    for (name, value) in rows {
+        let _ = writeln!(html, "<tr><th>{}</th><td>{}</td></tr>", escape(name), escape(value));
+    }
+    html.push_str("</table>\n");
+    html
+}
+
+/// A horizontal bar for each duration, scaled to the longest.
+fn bars(values: &[(&str, Duration)]) -> String {
+    let max = values.iter().map(|(_, d)| d.as_secs_f64()).fold(0.0, f64::max);
+    let height = values.len() as f64 * ROW_HEIGHT * 1.5;
+    let mut svg = svg_start(height);
+    for (i, (name, d)) in values.iter().enumerate() {
+        let y = i as f64 * ROW_HEIGHT * 1.5;
+        let width = if max > 0.0 {
+            (CHART_WIDTH - MARGIN_LEFT) * d.as_secs_f64() / max
+        } else {
+            0.0
+        };
+        let _ = writeln!(
+            svg,
+            r##"<text x="0" y="{:.1}">{}</text><rect x="{}" y="{:.1}" width="{:.1}" height="{}" fill="#4a7fc1"><title>{}</title></rect>"##,
+            y + ROW_HEIGHT * 0.75,
+            name,
+            MARGIN_LEFT,
+            y,
+            width,
+            ROW_HEIGHT,
+            ms(*d)
+        );
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// A row for the command and each process it started, with a line for each lap.
+fn timeline(run: &Run) -> String {
+    let walked = run.tree.map(|t| t.walk()).unwrap_or_default();
+    let rows = walked.len().max(1);
+    let height = rows as f64 * ROW_HEIGHT + MARGIN_BOTTOM;
+    let x = |d: Duration| MARGIN_LEFT + (CHART_WIDTH - MARGIN_LEFT) * ratio(d, run.real);
+    let mut svg = svg_start(height);
+
+    for (i, (depth, process)) in walked.iter().enumerate() {
+        let start = x(process.start);
+        let end = x(process.end.unwrap_or(run.real)).max(start + 1.0);
+        let y = i as f64 * ROW_HEIGHT;
+        let name = process.argv.first().map_or(process.pid.to_string(), |a| a.clone());
+        let _ = writeln!(
+            svg,
+            r##"<text x="{}" y="{:.1}">{}</text><rect x="{:.1}" y="{:.1}" width="{:.1}" height="{:.1}" fill="#6aa84f"><title>{} ({}): {} to {}</title></rect>"##,
+            depth * 8,
+            y + ROW_HEIGHT * 0.75,
+            escape(&name),
+            start,
+            y + 2.0,
+            end - start,
+            ROW_HEIGHT - 4.0,
+            escape(&process.argv.join(" ")),
+            process.pid,
+            ms(process.start),
+            process.end.map_or("-".into(), ms)
+        );
+    }
+
+    for lap in run.laps.unwrap_or_default() {
+        let _ = writeln!(
+            svg,
+            r##"<line x1="{0:.1}" x2="{0:.1}" y1="0" y2="{1:.1}" stroke="#cc4125" stroke-dasharray="3"><title>{2} at {3}</title></line>"##,
+            x(lap.at),
+            rows as f64 * ROW_HEIGHT,
+            escape(&lap.name),
+            ms(lap.at)
+        );
+    }
+
+    svg.push_str(&time_axis(height, run.real));
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// A line through the points, with time on the x axis and the y axis starting at zero.
+fn line_chart(points: &[(Duration, f64)], end: Duration, unit: &str) -> String {
+    let height = CHART_HEIGHT + MARGIN_TOP + MARGIN_BOTTOM;
+    let max = points.iter().map(|(_, v)| *v).fold(0.0, f64::max);
+    let y = |v: f64| MARGIN_TOP + CHART_HEIGHT * (1.0 - if max > 0.0 { v / max } else { 0.0 });
+    let x = |d: Duration| MARGIN_LEFT + (CHART_WIDTH - MARGIN_LEFT) * ratio(d, end);
+
+    let mut svg = svg_start(height);
+    let _ = writeln!(
+        svg,
+        r#"<text x="0" y="{:.1}">{:.1} {}</text><text x="0" y="{:.1}">0 {}</text>"#,
+        MARGIN_TOP + 4.0,
+        max,
+        unit,
+        MARGIN_TOP + CHART_HEIGHT,
+        unit
+    );
+    let line = points
+        .iter()
+        .map(|(d, v)| format!("{:.1},{:.1}", x(*d), y(*v)))
+        .collect::<Vec<_>>();
+    let _ = writeln!(
+        svg,
+        r##"<polyline fill="none" stroke="#4a7fc1" stroke-width="1.5" points="{}"/>"##,
+        line.join(" ")
+    );
+    svg.push_str(&time_axis(height, end));
+    svg.push_str("</svg>\n");
+    svg
+}
+
+fn svg_start(height: f64) -> String {
+    format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{0}\" height=\"{1:.0}\" viewBox=\"0 0 {0} {1:.0}\">\n",
+        CHART_WIDTH, height
+    )
+}
+
+/// Labels the start and end of the x axis at the bottom of a chart.
+fn time_axis(height: f64, end: Duration) -> String {
+    format!(
+        "<text x=\"{}\" y=\"{:.1}\">0</text><text x=\"{}\" y=\"{:.1}\" text-anchor=\"end\">{}</text>\n",
+        MARGIN_LEFT,
+        height - 6.0,
+        CHART_WIDTH,
+        height - 6.0,
+        ms(end)
+    )
+}
+
+fn ratio(d: Duration, end: Duration) -> f64 {
+    if end.is_zero() {
+        0.0
+    } else {
+        (d.as_secs_f64() / end.as_secs_f64()).min(1.0)
+    }
+}
+
+fn ms(d: Duration) -> String {
+    format!("{:.3} ms", d.as_secs_f64() * 1_000.0)
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sample::Sample;
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(
+            escape(r#"sh -c "a < b && c""#),
+            "sh -c &quot;a &lt; b &amp;&amp; c&quot;"
+        );
+    }
+
+    #[test]
+    fn test_render() {
+        let samples = [Sample {
+            at: Duration::from_millis(1),
+            rss: 1 << 20,
+            ..Default::default()
+        }];
+        let run = Run {
+            argv: vec!["echo".into(), "<hi>".into()],
+            pid: 42,
+            status: 0,
+            real: Duration::from_millis(2),
+            user: Duration::from_millis(1),
+            sys: Duration::ZERO,
+            tree: None,
+            laps: None,
+            samples: Some(&samples),
+        };
+
+        let html = render(&run, &[("os", "Linux".into())]);
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("<code>echo &lt;hi&gt;</code>"));
+        assert!(html.contains("<tr><th>status</th><td>exited with 0</td></tr>"));
+        assert!(html.contains("<h2>Memory</h2>"));
+        assert!(!html.contains("<h2>Timeline</h2>"));
+        assert!(html.contains("<tr><th>os</th><td>Linux</td></tr>"));
+    }
+}
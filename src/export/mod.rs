@@ -0,0 +1,54 @@
+//! Writing the results of a run to files which can be opened by other tools.
+
+pub mod chrome;
+pub mod html;
+
+use crate::command::Command;
+use crate::ffi;
+use crate::marker::Lap;
+use crate::sample::Sample;
+use crate::trace::Tree;
+use std::time::Duration;
+
+const NO_DATA: &str = "-";
+
+/// Everything recorded about a run of the command.
+pub struct Run<'a> {
+    pub argv: Vec<String>,
+    pub pid: libc::pid_t,
+    /// The wait status of the command.
+    pub status: i32,
+    pub real: Duration,
+    pub user: Duration,
+    pub sys: Duration,
+    pub tree: Option<&'a Tree>,
+    pub laps: Option<&'a [Lap]>,
+    pub samples: Option<&'a [Sample]>,
+}
+
+/// Describes the system and the options which affect the command, e.g.: for the environment section of a report.
+pub fn fingerprint(command: &Command) -> Vec<(&'static str, String)> {
+    let or_none = |v: Vec<String>| if v.is_empty() { NO_DATA.into() } else { v.join(", ") };
+    vec![
+        ("timers", env!("CARGO_PKG_VERSION").into()),
+        ("os", os_info::get().to_string()),
+        ("kernel", nix::sys::utsname::uname().release().into()),
+        ("cpu_count", ffi::cpu_count().map_or(NO_DATA.into(), |n| n.to_string())),
+        (
+            "mem_total",
+            ffi::mem::memory_total().map_or(NO_DATA.into(), |b| b.to_string()),
+        ),
+        ("isolation", or_none(command.get_isolation().describe())),
+        (
+            "limits",
+            or_none(command.get_limits().iter().map(|l| l.to_string()).collect()),
+        ),
+        ("env", command.get_environment().describe()),
+        (
+            "chdir",
+            command
+                .get_current_dir()
+                .map_or(NO_DATA.into(), |d| d.display().to_string()),
+        ),
+    ]
+}
@@ -3,8 +3,8 @@ use std::{cmp, fs};
 
 fn read_number_from_line(s: &str) -> Result<i64> {
     s.chars()
-        .skip_while(|c| !c.is_digit(10))
-        .take_while(|c| c.is_digit(10))
+        .skip_while(|c| !c.is_ascii_digit())
+        .take_while(|c| c.is_ascii_digit())
         .collect::<String>()
         .parse()
         .map_err(|e| anyhow!("{}", e))
@@ -59,3 +59,21 @@ pub fn memory_available() -> Result<u64> {
 
     Ok(m_avail as u64 * 1024)
 }
+
+pub fn swap_used() -> Result<u64> {
+    let mut s_total = None;
+    let mut s_free = None;
+    let f = fs::read_to_string("/proc/meminfo")?;
+    for line in f.lines() {
+        if line.starts_with("SwapTotal:") {
+            s_total = Some(read_number_from_line(line)?)
+        } else if line.starts_with("SwapFree:") {
+            s_free = Some(read_number_from_line(line)?)
+        }
+    }
+
+    match (s_total, s_free) {
+        (Some(total), Some(free)) => Ok((total - free) as u64 * 1024),
+        _ => bail!("Failed to parse /proc/meminfo for swap usage"),
+    }
+}
@@ -1,12 +1,12 @@
 #[cfg(target_os = "macos")]
 #[path = "darwin.rs"]
-mod mem;
+mod platform;
 
 #[cfg(not(target_os = "macos"))]
 #[path = "linux.rs"]
-mod mem;
+mod platform;
 
-pub use mem::*;
+pub use platform::*;
 
 use anyhow::Result;
 use crate::ffi::sysconf;
@@ -1,18 +1,62 @@
 pub mod mem;
+#[cfg(target_os = "linux")]
+pub mod proc;
+#[cfg(target_os = "linux")]
+pub mod ptrace;
+#[cfg(target_os = "linux")]
+pub mod syscalls;
 
-use anyhow::{bail, Result};
+use anyhow::{bail, Context, Result};
 use nix::errno::{errno, Errno};
+use nix::fcntl::OFlag;
+use nix::sys::signal::{raise, signal, SigHandler, Signal};
 use std::mem::MaybeUninit;
-use std::time::Duration;
+use std::os::unix::io::RawFd;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
 
 pub fn cpu_count() -> Result<u32> {
     sysconf(libc::_SC_NPROCESSORS_ONLN).map(|x| x as u32)
 }
 
+pub fn load_average() -> Result<[f64; 3]> {
+    let mut loads = [0.0; 3];
+    // SAFETY: the buffer holds exactly the number of elements we ask for
+    let n = unsafe { libc::getloadavg(loads.as_mut_ptr(), 3) };
+    if n != 3 {
+        bail!("Call to getloadavg failed, returned: {}", n);
+    }
+
+    Ok(loads)
+}
+
 pub fn timeval_to_duration(t: libc::timeval) -> Duration {
     Duration::new(t.tv_sec as u64, (t.tv_usec as u32) * 1_000)
 }
 
+/// The peak resident set size in bytes.
+pub fn max_rss(usage: &libc::rusage) -> u64 {
+    // NOTE: On Linux this value is in kilobytes
+    usage.ru_maxrss as u64 * if cfg!(target_os = "linux") { 1024 } else { 1 }
+}
+
+/// Turns the -1 a libc call returns on failure into the error in `errno`. Doesn't allocate, so it can be used in a
+/// child between `fork` and `exec`.
+pub fn cvt(r: libc::c_int) -> std::io::Result<libc::c_int> {
+    if r == -1 {
+        Err(std::io::Error::last_os_error())
+    } else {
+        Ok(r)
+    }
+}
+
+/// Creates a pipe whose ends are closed on exec. They're set atomically, so a child forked by another thread in the
+/// meantime can't inherit them.
+pub fn cloexec_pipe() -> Result<(RawFd, RawFd)> {
+    nix::unistd::pipe2(OFlag::O_CLOEXEC).context("Failed to create pipe")
+}
+
 pub fn sysconf(var: libc::c_int) -> Result<i64> {
     // SAFETY: we're checking the return code and errno, should be good enough for our use cases
     let raw = unsafe {
@@ -27,12 +71,54 @@ pub fn sysconf(var: libc::c_int) -> Result<i64> {
     Ok(raw)
 }
 
-pub fn wait_for_pid(pid: libc::pid_t) -> Result<(i32, libc::rusage)> {
+/// Reads one of the system's clocks, e.g.: `CLOCK_REALTIME` is the time since the Unix epoch.
+pub fn clock_gettime(clock: libc::clockid_t) -> Result<Duration> {
+    let mut ts = MaybeUninit::<libc::timespec>::uninit();
+    // SAFETY: the timespec is written to if the call succeeds
+    if unsafe { libc::clock_gettime(clock, ts.as_mut_ptr()) } == -1 {
+        bail!("Call to clock_gettime failed, errno: {}", Errno::last());
+    }
+
+    // SAFETY: we have asserted that the call succeeded
+    let ts = unsafe { ts.assume_init() };
+    Ok(Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32))
+}
+
+/// What was seen of a child while waiting for it to terminate.
+#[derive(Debug, Clone)]
+pub struct Waited {
+    pub status: i32,
+    /// When we saw it terminate, before reading anything else about it. This is when real time ends.
+    pub ended: Instant,
+    pub usage: libc::rusage,
+    pub stopped: Stopped,
+    /// The name of the executable it was last running, read before it was reaped. Core files are named after it.
+    pub comm: Option<String>,
+}
+
+/// Waits for the child to terminate, returning its status, resource usage and the times it was stopped.
+pub fn wait_for_pid(pid: libc::pid_t) -> Result<Waited> {
     let mut usage: MaybeUninit<libc::rusage> = MaybeUninit::uninit();
     let mut status = 0;
-    let options = 0;
+    // Also return when it's stopped or continued, so we know how long it was stopped for
+    let options = libc::WUNTRACED | libc::WCONTINUED;
+    let mut stops = Stops::default();
+    #[cfg_attr(not(target_os = "linux"), allow(unused_mut))]
+    let mut ended = None;
+    #[cfg_attr(not(target_os = "linux"), allow(unused_mut))]
+    let mut comm = None;
 
     loop {
+        #[cfg(target_os = "linux")]
+        if let code @ (libc::CLD_EXITED | libc::CLD_KILLED | libc::CLD_DUMPED) = peek(pid)? {
+            // NOTE: REAL_TIMER END: as soon as we see the forked process has terminated
+            ended = Some(Instant::now());
+            // Only a core file is named after it, so there's no need to read it otherwise
+            if code == libc::CLD_DUMPED {
+                comm = proc::Stat::read(pid).ok().map(|s| s.comm);
+            }
+        }
+
         let r = unsafe {
             Errno::clear();
             libc::wait4(
@@ -44,17 +130,180 @@ pub fn wait_for_pid(pid: libc::pid_t) -> Result<(i32, libc::rusage)> {
         };
 
         if r == -1 {
+            if Errno::last() == Errno::EINTR {
+                continue;
+            }
             bail!("Call to wait4 failed, errno: {}", Errno::last());
         }
 
-        // The child process we were waiting for (pid) terminated
+        // The child process we were waiting for (pid) changed state
         if r == pid {
-            break;
+            if libc::WIFSTOPPED(status) {
+                stops.stopped(libc::WSTOPSIG(status));
+            } else if libc::WIFCONTINUED(status) {
+                stops.continued();
+            } else {
+                break;
+            }
         }
     }
 
     // SAFETY: we have asserted that the return condition is not an error
-    Ok((status, unsafe { usage.assume_init() }))
+    Ok(Waited {
+        status,
+        ended: ended.unwrap_or_else(Instant::now),
+        usage: unsafe { usage.assume_init() },
+        stopped: stops.finish(),
+        comm,
+    })
+}
+
+/// Waits for the child to change state like `wait4`, but leaves the change to be collected. Returns the `si_code` of
+/// the change, if it has terminated it's a zombie until it's reaped and can still be read from procfs.
+#[cfg(target_os = "linux")]
+fn peek(pid: libc::pid_t) -> Result<libc::c_int> {
+    let mut info = MaybeUninit::<libc::siginfo_t>::zeroed();
+    let options = libc::WEXITED | libc::WSTOPPED | libc::WCONTINUED | libc::WNOWAIT;
+    loop {
+        // SAFETY: the siginfo is written to if the call succeeds
+        if unsafe { libc::waitid(libc::P_PID, pid as libc::id_t, info.as_mut_ptr(), options) } == -1 {
+            if Errno::last() == Errno::EINTR {
+                continue;
+            }
+            bail!("Call to waitid failed, errno: {}", Errno::last());
+        }
+
+        // SAFETY: we have asserted that the call succeeded
+        return Ok(unsafe { info.assume_init_ref() }.si_code);
+    }
+}
+
+/// Set when we're sent SIGTSTP, e.g.: when Ctrl-Z is pressed in the terminal.
+static SUSPENDED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_sigtstp(_: libc::c_int) {
+    SUSPENDED.store(true, Ordering::Relaxed);
+}
+
+/// Called with true just before we stop along with the child, and with false once we've been continued.
+static ON_SUSPEND: Mutex<Option<fn(bool)>> = Mutex::new(None);
+
+/// Sets what to do around stopping along with the child, e.g.: to give the shell back a usable terminal.
+pub fn on_suspend(hook: Option<fn(bool)>) {
+    *ON_SUSPEND.lock().unwrap_or_else(|e| e.into_inner()) = hook;
+}
+
+/// Called before waiting for the child, so Ctrl-Z doesn't stop us before we've seen the child stop and know when it
+/// did. We then stop ourselves, so the shell gets the terminal back and `fg` continues both of us.
+pub fn handle_job_control() -> Result<()> {
+    // SAFETY: the handler only stores to an atomic. It's reset in the child when it execs.
+    unsafe { signal(Signal::SIGTSTP, SigHandler::Handler(on_sigtstp))? };
+    Ok(())
+}
+
+/// The times a process was stopped by a signal.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Stopped {
+    pub time: Duration,
+    /// The signal which stopped it each time, in order.
+    pub signals: Vec<libc::c_int>,
+}
+
+/// Adds up the time a process spends stopped, from seeing it stop and continue.
+#[derive(Debug, Default)]
+pub struct Stops {
+    since: Option<Instant>,
+    stopped: Stopped,
+}
+
+impl Stops {
+    pub fn stopped(&mut self, signal: libc::c_int) {
+        if self.since.is_none() {
+            self.since = Some(Instant::now());
+            self.stopped.signals.push(signal);
+        }
+        // It was suspended from the terminal, so we stop along with it
+        if SUSPENDED.swap(false, Ordering::Relaxed) {
+            let hook = *ON_SUSPEND.lock().unwrap_or_else(|e| e.into_inner());
+            if let Some(hook) = hook {
+                hook(true);
+            }
+            // Only returns once we've been continued
+            let _ = raise(Signal::SIGSTOP);
+            if let Some(hook) = hook {
+                hook(false);
+            }
+        }
+    }
+
+    pub fn continued(&mut self) {
+        if let Some(since) = self.since.take() {
+            self.stopped.time += since.elapsed();
+        }
+    }
+
+    /// Includes the current stop if it terminated while stopped.
+    pub fn finish(mut self) -> Stopped {
+        self.continued();
+        self.stopped
+    }
+}
+
+/// Waits for a state change in any child or tracee of the calling thread, returning its pid, status and resource
+/// usage. The resource usage is only meaningful when the returned pid is our child and has terminated.
+#[cfg(target_os = "linux")]
+pub fn wait_any() -> Result<(libc::pid_t, i32, libc::rusage)> {
+    wait_task(-1)
+}
+
+/// Waits for a state change in the given task, which may be a thread, or any task if it's -1.
+/// Only tasks which are children or tracees of the calling thread are waited for, so we don't take the children of
+/// other threads from them.
+#[cfg(target_os = "linux")]
+pub fn wait_task(tid: libc::pid_t) -> Result<(libc::pid_t, i32, libc::rusage)> {
+    let mut usage: MaybeUninit<libc::rusage> = MaybeUninit::uninit();
+    let mut status = 0;
+
+    loop {
+        let r = unsafe {
+            Errno::clear();
+            libc::wait4(
+                tid,
+                (&mut status) as *mut libc::c_int,
+                libc::__WALL | libc::__WNOTHREAD,
+                usage.as_mut_ptr(),
+            )
+        };
+
+        if r == -1 {
+            if Errno::last() == Errno::EINTR {
+                continue;
+            }
+            bail!("Call to wait4 failed, errno: {}", Errno::last());
+        }
+
+        // SAFETY: we have asserted that the return condition is not an error
+        return Ok((r, status, unsafe { usage.assume_init() }));
+    }
+}
+
+/// Returns a file descriptor referring to the process, which becomes readable once it has terminated.
+/// Requires Linux 5.3 or later.
+#[cfg(target_os = "linux")]
+pub fn pidfd_open(pid: libc::pid_t) -> Result<std::os::unix::io::OwnedFd> {
+    use std::os::unix::io::{FromRawFd, OwnedFd, RawFd};
+
+    // SAFETY: pidfd_open takes a pid and flags, and returns a new file descriptor or -1
+    let fd = unsafe {
+        Errno::clear();
+        libc::syscall(libc::SYS_pidfd_open, pid, 0)
+    };
+    if fd == -1 {
+        bail!("Call to pidfd_open failed, errno: {}", Errno::last());
+    }
+
+    // SAFETY: the file descriptor was just opened, and nothing else owns it
+    Ok(unsafe { OwnedFd::from_raw_fd(fd as RawFd) })
 }
 
 #[cfg(test)]
@@ -79,4 +328,24 @@ mod tests {
             Duration::new(42, 42000)
         );
     }
+
+    #[test]
+    fn test_stops() {
+        assert_eq!(Stops::default().finish(), Stopped::default());
+
+        let mut stops = Stops::default();
+        stops.stopped(libc::SIGSTOP);
+        std::thread::sleep(Duration::from_millis(10));
+        stops.continued();
+        // Continuing again without a stop in between doesn't add anything
+        stops.continued();
+        let time = stops.stopped.time;
+        assert!(time >= Duration::from_millis(10));
+
+        // Terminating while stopped counts the time up to now
+        stops.stopped(libc::SIGTSTP);
+        let stopped = stops.finish();
+        assert!(stopped.time > time);
+        assert_eq!(stopped.signals, vec![libc::SIGSTOP, libc::SIGTSTP]);
+    }
 }
@@ -0,0 +1,308 @@
+//! Reading information about a process from procfs.
+
+use anyhow::{anyhow, bail, Context, Result};
+use std::collections::HashMap;
+use std::fs;
+use std::time::Duration;
+
+use crate::ffi::sysconf;
+
+/// The fields we use from `/proc/<pid>/stat`, see `man 5 proc` for details.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stat {
+    /// The filename of the executable, without its path.
+    pub comm: String,
+    pub ppid: libc::pid_t,
+    /// Page faults which didn't and did need to load a page from disk.
+    pub minflt: u64,
+    pub majflt: u64,
+    /// Time spent in user mode.
+    pub utime: Duration,
+    /// Time spent in kernel mode.
+    pub stime: Duration,
+    /// Time spent in user and kernel mode by children the process has waited for.
+    pub cutime: Duration,
+    pub cstime: Duration,
+    /// When the process started, relative to system boot.
+    pub start_time: Duration,
+}
+
+impl Stat {
+    pub fn read(pid: libc::pid_t) -> Result<Stat> {
+        let path = format!("/proc/{}/stat", pid);
+        // The command name may not be valid UTF-8, since it's truncated to 15 bytes and can be set to anything
+        let contents = fs::read(&path).with_context(|| format!("Failed to read {}", path))?;
+        Stat::parse(&String::from_utf8_lossy(&contents), clock_ticks()?)
+            .with_context(|| format!("Failed to parse {}", path))
+    }
+
+    fn parse(s: &str, ticks_per_sec: u64) -> Result<Stat> {
+        // The command name is in parentheses and may contain spaces or parentheses itself, so end at the last one.
+        // Fields are numbered from 1, and the field after the command name is the 3rd.
+        let (comm_start, comm_end) = match (s.find('('), s.rfind(')')) {
+            (Some(start), Some(end)) if start < end => (start, end),
+            _ => bail!("Missing command name"),
+        };
+        let fields = s[comm_end + 1..].split_whitespace().collect::<Vec<_>>();
+        let field = |n: usize| -> Result<u64> {
+            let value = fields.get(n - 3).ok_or_else(|| anyhow!("Missing field {}", n))?;
+            value.parse().map_err(|e| anyhow!("Invalid field {}: {}", n, e))
+        };
+        let ticks = |n: u64| Duration::from_nanos(n * 1_000_000_000 / ticks_per_sec);
+
+        Ok(Stat {
+            comm: s[comm_start + 1..comm_end].to_string(),
+            ppid: field(4)? as libc::pid_t,
+            minflt: field(10)?,
+            majflt: field(12)?,
+            utime: ticks(field(14)?),
+            stime: ticks(field(15)?),
+            cutime: ticks(field(16)?),
+            cstime: ticks(field(17)?),
+            start_time: ticks(field(22)?),
+        })
+    }
+}
+
+/// The fields we use from `/proc/<pid>/status`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Status {
+    /// The thread group (process) this task belongs to.
+    pub tgid: libc::pid_t,
+    /// Peak resident set size in bytes, missing for kernel threads and zombies.
+    pub vm_hwm: Option<u64>,
+    /// Current resident set size in bytes, missing for kernel threads and zombies.
+    pub vm_rss: Option<u64>,
+    pub threads: u64,
+    /// Context switches where the process gave up the cpu, or was made to.
+    pub voluntary_csw: u64,
+    pub involuntary_csw: u64,
+}
+
+impl Status {
+    pub fn read(pid: libc::pid_t) -> Result<Status> {
+        let path = format!("/proc/{}/status", pid);
+        let contents = fs::read_to_string(&path).with_context(|| format!("Failed to read {}", path))?;
+        Status::parse(&contents).with_context(|| format!("Failed to parse {}", path))
+    }
+
+    fn parse(s: &str) -> Result<Status> {
+        let mut tgid = None;
+        let mut vm_hwm = None;
+        let mut vm_rss = None;
+        let mut threads = 0;
+        let mut voluntary_csw = 0;
+        let mut involuntary_csw = 0;
+        for line in s.lines() {
+            let (key, value) = match line.split_once(':') {
+                Some((key, value)) => (key, value.trim()),
+                None => continue,
+            };
+            // Sizes are always given in kB
+            let kb = |v: &str| v.trim_end_matches(" kB").parse::<u64>().map(|n| n * 1024);
+            match key {
+                "Tgid" => tgid = Some(value.parse()?),
+                "VmHWM" => vm_hwm = Some(kb(value)?),
+                "VmRSS" => vm_rss = Some(kb(value)?),
+                "Threads" => threads = value.parse()?,
+                "voluntary_ctxt_switches" => voluntary_csw = value.parse()?,
+                "nonvoluntary_ctxt_switches" => involuntary_csw = value.parse()?,
+                _ => {}
+            }
+        }
+
+        Ok(Status {
+            tgid: tgid.ok_or_else(|| anyhow!("Missing Tgid"))?,
+            vm_hwm,
+            vm_rss,
+            threads,
+            voluntary_csw,
+            involuntary_csw,
+        })
+    }
+}
+
+/// The fields we use from `/proc/<pid>/io`, which is only readable by the process's owner.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Io {
+    /// Bytes read from and written to storage, reads served from the page cache aren't included.
+    pub read_bytes: u64,
+    pub write_bytes: u64,
+}
+
+impl Io {
+    pub fn read(pid: libc::pid_t) -> Result<Io> {
+        let path = format!("/proc/{}/io", pid);
+        let contents = fs::read_to_string(&path).with_context(|| format!("Failed to read {}", path))?;
+        Io::parse(&contents).with_context(|| format!("Failed to parse {}", path))
+    }
+
+    fn parse(s: &str) -> Result<Io> {
+        let field = |name: &str| -> Result<u64> {
+            let line = s
+                .lines()
+                .find_map(|line| line.strip_prefix(name)?.strip_prefix(':'))
+                .ok_or_else(|| anyhow!("Missing {}", name))?;
+            Ok(line.trim().parse()?)
+        };
+
+        Ok(Io {
+            read_bytes: field("read_bytes")?,
+            write_bytes: field("write_bytes")?,
+        })
+    }
+}
+
+/// Returns how many file descriptors the process has open.
+pub fn fd_count(pid: libc::pid_t) -> Result<usize> {
+    let path = format!("/proc/{}/fd", pid);
+    Ok(fs::read_dir(&path)
+        .with_context(|| format!("Failed to read {}", path))?
+        .count())
+}
+
+/// Returns the command line of the process, which is empty for zombies and kernel threads.
+pub fn cmdline(pid: libc::pid_t) -> Result<Vec<String>> {
+    let path = format!("/proc/{}/cmdline", pid);
+    let contents = fs::read(&path).with_context(|| format!("Failed to read {}", path))?;
+    Ok(contents
+        .split(|&b| b == 0)
+        .filter(|arg| !arg.is_empty())
+        .map(|arg| String::from_utf8_lossy(arg).into_owned())
+        .collect())
+}
+
+/// Returns the pids of all processes that are currently running.
+pub fn pids() -> Result<Vec<libc::pid_t>> {
+    let mut pids = vec![];
+    for entry in fs::read_dir("/proc")? {
+        if let Some(pid) = entry?.file_name().to_str().and_then(|name| name.parse().ok()) {
+            pids.push(pid);
+        }
+    }
+
+    Ok(pids)
+}
+
+/// Whether the kernel lists the children of each thread, which it only does if it was built with
+/// `CONFIG_PROC_CHILDREN`.
+pub fn lists_children() -> bool {
+    fs::metadata("/proc/thread-self/children").is_ok()
+}
+
+/// Returns the children started by any of the process's threads, see `lists_children`.
+pub fn children(pid: libc::pid_t) -> Vec<libc::pid_t> {
+    let tasks = match fs::read_dir(format!("/proc/{}/task", pid)) {
+        Ok(tasks) => tasks,
+        Err(_) => return vec![],
+    };
+
+    let mut children = vec![];
+    // A thread may exit while we're reading, along with its file
+    for task in tasks.flatten() {
+        if let Ok(contents) = fs::read_to_string(task.path().join("children")) {
+            children.extend(
+                contents
+                    .split_whitespace()
+                    .filter_map(|pid| pid.parse::<libc::pid_t>().ok()),
+            );
+        }
+    }
+
+    children
+}
+
+/// Returns the root and all of its descendants, given the stats of every running process.
+pub fn descendants(root: libc::pid_t, stats: &HashMap<libc::pid_t, Stat>) -> Vec<libc::pid_t> {
+    let mut found = vec![root];
+    let mut i = 0;
+    while i < found.len() {
+        let parent = found[i];
+        found.extend(stats.iter().filter(|(_, s)| s.ppid == parent).map(|(pid, _)| *pid));
+        i += 1;
+    }
+
+    found
+}
+
+/// Returns the time since the system booted, which is what a process's start time is relative to.
+pub fn uptime() -> Result<Duration> {
+    let contents = fs::read_to_string("/proc/uptime").context("Failed to read /proc/uptime")?;
+    let secs = contents
+        .split_whitespace()
+        .next()
+        .and_then(|s| s.parse::<f64>().ok())
+        .ok_or_else(|| anyhow!("Failed to parse /proc/uptime: {:?}", contents))?;
+
+    Ok(Duration::from_secs_f64(secs))
+}
+
+/// Number of clock ticks per second, which is the unit of times in `/proc/<pid>/stat`.
+pub fn clock_ticks() -> Result<u64> {
+    sysconf(libc::_SC_CLK_TCK).map(|x| x as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stat_parse() {
+        let s = "4242 (my (weird) cmd) S 1 4242 4242 0 -1 4194560 1234 0 5 0 250 125 0 0 20 0 3 0 1000 \
+                 12345678 910 18446744073709551615 1 1 0 0 0 0 0 0 0 0 0 0 17 2 0 0 0 0 0";
+        let stat = Stat::parse(s, 100).unwrap();
+        assert_eq!(stat.comm, "my (weird) cmd");
+        assert_eq!(stat.ppid, 1);
+        assert_eq!(stat.minflt, 1234);
+        assert_eq!(stat.majflt, 5);
+        assert_eq!(stat.utime, Duration::from_millis(2500));
+        assert_eq!(stat.stime, Duration::from_millis(1250));
+        assert_eq!(stat.cutime, Duration::ZERO);
+        assert_eq!(stat.start_time, Duration::from_secs(10));
+        assert!(Stat::parse("4242 (cmd) S 1", 100).is_err());
+        assert!(Stat::parse("garbage", 100).is_err());
+    }
+
+    #[test]
+    fn test_status_parse() {
+        let s = "Name:\tcat\nUmask:\t0022\nState:\tR (running)\nTgid:\t4242\nPid:\t4243\n\
+                 VmPeak:\t    8000 kB\nVmHWM:\t    1024 kB\nVmRSS:\t     900 kB\nThreads:\t1\n\
+                 voluntary_ctxt_switches:\t42\nnonvoluntary_ctxt_switches:\t7\n";
+        let status = Status::parse(s).unwrap();
+        assert_eq!(status.tgid, 4242);
+        assert_eq!(status.vm_hwm, Some(1024 * 1024));
+        assert_eq!(status.vm_rss, Some(900 * 1024));
+        assert_eq!(status.threads, 1);
+        assert_eq!(status.voluntary_csw, 42);
+        assert_eq!(status.involuntary_csw, 7);
+
+        let status = Status::parse("Name:\tkthreadd\nTgid:\t2\n").unwrap();
+        assert_eq!(status.vm_hwm, None);
+        assert!(Status::parse("Name:\tcat\n").is_err());
+    }
+
+    #[test]
+    fn test_io_parse() {
+        let s = "rchar: 4242\nwchar: 100\nsyscr: 10\nsyscw: 2\nread_bytes: 8192\nwrite_bytes: 4096\n\
+                 cancelled_write_bytes: 0\n";
+        let io = Io::parse(s).unwrap();
+        assert_eq!(io.read_bytes, 8192);
+        assert_eq!(io.write_bytes, 4096);
+        assert!(Io::parse("rchar: 4242\n").is_err());
+    }
+
+    #[test]
+    fn test_children() {
+        if !lists_children() {
+            return;
+        }
+
+        let child = crate::Command::new("sh")
+            .args(["-c", "sleep 0.5 & sleep 0.5 & wait"])
+            .spawn()
+            .unwrap();
+        std::thread::sleep(Duration::from_millis(200));
+        assert_eq!(children(child.pid()).len(), 2);
+        child.wait().unwrap();
+    }
+}
@@ -0,0 +1,171 @@
+//! Thin wrappers around `ptrace`, see `man 2 ptrace` for details.
+
+use anyhow::{bail, Result};
+use nix::errno::{errno, Errno};
+use std::ptr;
+
+/// Follow every new process and thread, and stop them when they exec or exit.
+pub const OPTIONS: libc::c_int = libc::PTRACE_O_TRACEFORK
+    | libc::PTRACE_O_TRACEVFORK
+    | libc::PTRACE_O_TRACECLONE
+    | libc::PTRACE_O_TRACEEXEC
+    | libc::PTRACE_O_TRACEEXIT;
+
+/// Which ptrace event caused a stop, if any.
+pub fn event(status: i32) -> i32 {
+    (status >> 16) & 0xffff
+}
+
+fn request(request: libc::c_uint, pid: libc::pid_t, addr: usize, data: usize) -> Result<libc::c_long> {
+    // SAFETY: the only request which writes to our memory is given a pointer which outlives the call
+    let r = unsafe {
+        Errno::clear();
+        libc::ptrace(request, pid, addr as *mut libc::c_void, data as *mut libc::c_void)
+    };
+
+    // Some requests return data, so -1 is only an error if errno was set
+    if r == -1 && errno() != 0 {
+        bail!("Call to ptrace({}) failed, errno: {}", request, Errno::last());
+    }
+
+    Ok(r)
+}
+
+/// Attaches to the process without stopping it.
+pub fn seize(pid: libc::pid_t, options: libc::c_int) -> Result<()> {
+    request(libc::PTRACE_SEIZE, pid, 0, options as usize).map(drop)
+}
+
+/// Resumes a stopped tracee, delivering `signal` to it if it's non-zero.
+pub fn cont(pid: libc::pid_t, signal: i32) -> Result<()> {
+    request(libc::PTRACE_CONT, pid, 0, signal as usize).map(drop)
+}
+
+/// Lets a tracee in group-stop stay stopped, while still reporting events like `SIGCONT`.
+pub fn listen(pid: libc::pid_t) -> Result<()> {
+    request(libc::PTRACE_LISTEN, pid, 0, 0).map(drop)
+}
+
+/// Stops a running tracee, which is then reported like any other stop.
+pub fn interrupt(pid: libc::pid_t) -> Result<()> {
+    request(libc::PTRACE_INTERRUPT, pid, 0, 0).map(drop)
+}
+
+/// Stops tracing a stopped tracee and resumes it, delivering `signal` to it if it's non-zero.
+pub fn detach(pid: libc::pid_t, signal: i32) -> Result<()> {
+    request(libc::PTRACE_DETACH, pid, 0, signal as usize).map(drop)
+}
+
+/// Returns the message for the last event, e.g.: the new pid after a fork.
+pub fn event_msg(pid: libc::pid_t) -> Result<u64> {
+    let mut msg: libc::c_ulong = 0;
+    request(libc::PTRACE_GETEVENTMSG, pid, 0, ptr::addr_of_mut!(msg) as usize)?;
+    Ok(msg as u64)
+}
+
+/// The signal reported for syscall-stops, when `PTRACE_O_TRACESYSGOOD` is set.
+pub const SYSCALL_STOP: i32 = libc::SIGTRAP | 0x80;
+
+// Not in the version of libc we use, see `linux/ptrace.h`
+const PTRACE_GET_SYSCALL_INFO: libc::c_uint = 0x420e;
+const PTRACE_SYSCALL_INFO_ENTRY: u8 = 1;
+const PTRACE_SYSCALL_INFO_EXIT: u8 = 2;
+
+/// Mirrors `struct ptrace_syscall_info`, the union at the end is large enough for its largest member.
+#[repr(C)]
+#[derive(Default)]
+#[allow(dead_code)]
+struct SyscallInfo {
+    op: u8,
+    pad: [u8; 3],
+    arch: u32,
+    instruction_pointer: u64,
+    stack_pointer: u64,
+    data: [u64; 8],
+}
+
+/// Why a tracee is in a syscall-stop.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyscallStop {
+    Entry { nr: u64, args: [u64; 6] },
+    Exit { rval: i64, is_error: bool },
+    Other,
+}
+
+/// Resumes a stopped tracee like `cont`, but also stops it at the next entry to or exit from a system call.
+pub fn syscall(pid: libc::pid_t, signal: i32) -> Result<()> {
+    request(libc::PTRACE_SYSCALL, pid, 0, signal as usize).map(drop)
+}
+
+/// Returns the system call a tracee in a syscall-stop is entering or leaving, requires Linux 5.3 or later.
+pub fn syscall_info(pid: libc::pid_t) -> Result<SyscallStop> {
+    let mut info = SyscallInfo::default();
+    let size = std::mem::size_of::<SyscallInfo>();
+    request(PTRACE_GET_SYSCALL_INFO, pid, size, ptr::addr_of_mut!(info) as usize)?;
+
+    let d = info.data;
+    Ok(match info.op {
+        PTRACE_SYSCALL_INFO_ENTRY => SyscallStop::Entry {
+            nr: d[0],
+            args: [d[1], d[2], d[3], d[4], d[5], d[6]],
+        },
+        // `is_error` is a single byte following `rval`
+        PTRACE_SYSCALL_INFO_EXIT => SyscallStop::Exit {
+            rval: d[0] as i64,
+            is_error: d[1].to_ne_bytes()[0] != 0,
+        },
+        _ => SyscallStop::Other,
+    })
+}
+
+/// Reads a NUL-terminated string from a tracee's memory, e.g.: a path passed to a system call.
+pub fn read_string(pid: libc::pid_t, addr: u64) -> Result<Vec<u8>> {
+    // Reads are split at page boundaries (of the smallest page size), since the next page may not be mapped
+    const PAGE: usize = 4096;
+
+    let mut string = vec![];
+    let mut addr = addr as usize;
+    while string.len() < libc::PATH_MAX as usize {
+        let mut buf = [0u8; PAGE];
+        let len = PAGE - addr % PAGE;
+        let local = libc::iovec {
+            iov_base: buf.as_mut_ptr() as *mut libc::c_void,
+            iov_len: len,
+        };
+        let remote = libc::iovec {
+            iov_base: addr as *mut libc::c_void,
+            iov_len: len,
+        };
+        // SAFETY: the local buffer is at least `len` bytes long
+        let n = unsafe { libc::process_vm_readv(pid, &local, 1, &remote, 1, 0) };
+        if n <= 0 {
+            bail!("Call to process_vm_readv failed, errno: {}", Errno::last());
+        }
+
+        let chunk = &buf[..n as usize];
+        if let Some(end) = chunk.iter().position(|&b| b == 0) {
+            string.extend_from_slice(&chunk[..end]);
+            return Ok(string);
+        }
+        string.extend_from_slice(chunk);
+        addr += chunk.len();
+    }
+
+    bail!("String is longer than PATH_MAX")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_string() {
+        // Our own memory can be read the same way as a tracee's
+        let pid = std::process::id() as libc::pid_t;
+        let s = std::ffi::CString::new("/some/path").unwrap();
+        assert_eq!(read_string(pid, s.as_ptr() as u64).unwrap(), b"/some/path");
+
+        let long = std::ffi::CString::new(vec![b'a'; 3 * 4096]).unwrap();
+        assert!(read_string(pid, long.as_ptr() as u64).is_err());
+    }
+}
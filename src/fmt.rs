@@ -1,5 +1,61 @@
+use anyhow::{anyhow, bail, Result};
+use std::fmt;
+use std::str::FromStr;
 use std::time::Duration;
-use crate::cli::TimeFormat;
+
+#[derive(Debug, Clone, Copy)]
+pub enum TimeFormat {
+    Normal,
+    Seconds,
+    Milli,
+    Micro,
+    Nano,
+}
+
+impl fmt::Display for TimeFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Normal => "normal",
+            Self::Seconds => "seconds",
+            Self::Milli => "milli",
+            Self::Micro => "micro",
+            Self::Nano => "nano",
+        })
+    }
+}
+
+impl FromStr for TimeFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "normal" => Ok(Self::Normal),
+            "seconds" => Ok(Self::Seconds),
+            "milli" => Ok(Self::Milli),
+            "micro" => Ok(Self::Micro),
+            "nano" => Ok(Self::Nano),
+            _ => bail!("Unrecognised time format: '{}'", s),
+        }
+    }
+}
+
+/// Parses a number with an optional suffix, which the number is multiplied by.
+pub fn parse_suffixed(s: &str, suffixes: &[(&str, u64)]) -> Result<u64> {
+    let (number, multiplier) = suffixes
+        .iter()
+        .find_map(|(suffix, multiplier)| {
+            s.strip_suffix(suffix)
+                .or_else(|| s.strip_suffix(&suffix.to_lowercase()))
+                .map(|n| (n, *multiplier))
+        })
+        .unwrap_or((s, 1));
+
+    number
+        .parse::<u64>()
+        .map_err(|e| anyhow!("'{}': {}", s, e))?
+        .checked_mul(multiplier)
+        .ok_or_else(|| anyhow!("'{}' is too large", s))
+}
 
 /// Returns the milliseconds, with nanoseconds contained in an `f64`.
 fn duration_millis_f64(d: Duration) -> f64 {
@@ -40,10 +96,81 @@ pub fn duration_formatter(time_format: Option<TimeFormat>) -> impl Fn(Duration)
     }
 }
 
+/// Aligns rows of cells into columns, separated by two spaces.
+/// The first column is left aligned and the rest are right aligned, since they're usually numbers.
+pub fn table(rows: &[Vec<String>]) -> Vec<String> {
+    let mut widths = vec![];
+    for row in rows {
+        widths.resize(widths.len().max(row.len()), 0);
+        for (i, cell) in row.iter().enumerate() {
+            widths[i] = widths[i].max(cell.chars().count());
+        }
+    }
+
+    rows.iter()
+        .map(|row| {
+            let cells = row.iter().enumerate().map(|(i, cell)| {
+                if i == 0 {
+                    format!("{:<width$}", cell, width = widths[i])
+                } else {
+                    format!("{:>width$}", cell, width = widths[i])
+                }
+            });
+            cells.collect::<Vec<_>>().join("  ").trim_end().to_string()
+        })
+        .collect()
+}
+
+/// Quotes a string for use in JSON, escaping anything which isn't allowed in a JSON string.
+pub fn json_string(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Formats a time since the Unix epoch as an RFC 3339 timestamp in UTC, e.g.: `2021-11-29T08:15:30.123456Z`.
+pub fn timestamp(since_epoch: Duration) -> String {
+    let secs = since_epoch.as_secs();
+    let (days, secs) = ((secs / 86_400) as i64, secs % 86_400);
+    // Days since the epoch to a date in the Gregorian calendar, see:
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:06}Z",
+        year,
+        month,
+        day,
+        secs / 3_600,
+        secs / 60 % 60,
+        secs % 60,
+        since_epoch.subsec_micros()
+    )
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::cli::TimeFormat;
 
     #[test]
     fn test_duration_millis_f64() {
@@ -63,6 +190,24 @@ mod tests {
         assert_eq!(duration_nanos_u64(Duration::new(1, 42)), 1000000042);
     }
 
+    #[test]
+    fn test_parse_suffixed() {
+        let suffixes = [("ms", 1), ("s", 1000)];
+        assert_eq!(parse_suffixed("250ms", &suffixes).unwrap(), 250);
+        assert_eq!(parse_suffixed("2s", &suffixes).unwrap(), 2000);
+        assert_eq!(parse_suffixed("2k", &[("K", 1024)]).unwrap(), 2048);
+        assert_eq!(parse_suffixed("42", &suffixes).unwrap(), 42);
+        assert!(parse_suffixed("2m", &suffixes).is_err());
+        assert!(parse_suffixed("18446744073709551615s", &suffixes).is_err());
+    }
+
+    #[test]
+    fn test_time_format() {
+        assert!(matches!("milli".parse(), Ok(TimeFormat::Milli)));
+        assert!(matches!(TimeFormat::Nano.to_string().parse(), Ok(TimeFormat::Nano)));
+        assert!("millis".parse::<TimeFormat>().is_err());
+    }
+
     #[test]
     fn test_duration_formatter_none() {
         let fmt = duration_formatter(None);
@@ -108,4 +253,35 @@ mod tests {
         assert_eq!(fmt(Duration::new(0, 42)), "42ns");
         assert_eq!(fmt(Duration::new(1, 42)), "1000000042ns");
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_table() {
+        let rows = vec![
+            vec!["name".to_string(), "at".to_string()],
+            vec!["load".to_string(), "1.5µs".to_string()],
+            vec!["a".to_string(), "12.25ms".to_string()],
+        ];
+        assert_eq!(table(&rows), vec!["name       at", "load    1.5µs", "a     12.25ms"]);
+        assert!(table(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_json_string() {
+        assert_eq!(json_string("make -j4"), r#""make -j4""#);
+        assert_eq!(json_string("say \"hi\"\n"), r#""say \"hi\"\n""#);
+        assert_eq!(json_string("C:\\dir\t\u{1}"), r#""C:\\dir\t\u0001""#);
+    }
+
+    #[test]
+    fn test_timestamp() {
+        assert_eq!(timestamp(Duration::ZERO), "1970-01-01T00:00:00.000000Z");
+        assert_eq!(
+            timestamp(Duration::new(1_638_173_730, 123_456_789)),
+            "2021-11-29T08:15:30.123456Z"
+        );
+        assert_eq!(
+            timestamp(Duration::from_secs(951_782_400)),
+            "2000-02-29T00:00:00.000000Z"
+        );
+    }
+}
@@ -0,0 +1,224 @@
+//! Running several copies of the command at once, to measure throughput and how it scales.
+
+use crate::command::Command;
+use crate::ffi;
+use anyhow::{anyhow, bail, Result};
+use nix::sys::signal::{kill, Signal};
+use nix::unistd::Pid;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// One copy of the command, or one stage of a pipeline.
+#[derive(Debug, Clone)]
+pub struct Instance {
+    pub pid: libc::pid_t,
+    pub status: i32,
+    pub real: Duration,
+    pub user: Duration,
+    pub sys: Duration,
+    /// Peak resident set size in bytes.
+    pub max_rss: u64,
+}
+
+/// Copies of the command which were all started together.
+#[derive(Debug, Clone)]
+pub struct Batch {
+    /// From starting the first copy until the last one terminated.
+    pub wall: Duration,
+    pub instances: Vec<Instance>,
+}
+
+impl Batch {
+    pub fn jobs(&self) -> usize {
+        self.instances.len()
+    }
+
+    /// Copies completed per second.
+    pub fn throughput(&self) -> f64 {
+        self.jobs() as f64 / self.wall.as_secs_f64()
+    }
+}
+
+/// How a batch compares to the first batch of a sweep.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Scaling {
+    pub jobs: usize,
+    pub wall: Duration,
+    pub throughput: f64,
+    /// Throughput relative to the first batch.
+    pub speedup: f64,
+    /// Speedup relative to the increase in jobs, 1.0 is perfect scaling.
+    pub efficiency: f64,
+}
+
+/// Starts `jobs` copies of the command, and waits for all of them to terminate. The command's redirections are
+/// opened once and shared, so copies writing to the same file don't truncate each other's output.
+pub fn run(jobs: u32, command: &Command) -> Result<Batch> {
+    let stdio = command.get_stdio().open()?;
+    let mut command = command.clone();
+    command.stdio(Default::default());
+
+    let start = Instant::now();
+    let mut pids = vec![];
+    for _ in 0..jobs {
+        let spawned = command.spawn_with(|| stdio.apply(true, true)).and_then(|mut child| {
+            child.started()?;
+            Ok(child.pid())
+        });
+        match spawned {
+            Ok(pid) => pids.push(pid),
+            Err(e) => {
+                // Otherwise the copies already started would carry on without us
+                for pid in &pids {
+                    let _ = kill(Pid::from_raw(*pid), Signal::SIGKILL);
+                }
+                for pid in pids {
+                    let _ = ffi::wait_for_pid(pid);
+                }
+                return Err(e);
+            }
+        }
+    }
+    let instances = wait_all(pids, start)?;
+    let wall = instances.iter().map(|i| i.real).max().unwrap_or_default();
+
+    Ok(Batch { wall, instances })
+}
+
+/// Waits for each of the processes to terminate, `start` is when the first of them was started.
+pub fn wait_all(pids: Vec<libc::pid_t>, start: Instant) -> Result<Vec<Instance>> {
+    // Each process is waited for on its own thread, so we see when it terminated rather than when we got to it
+    let waiters = pids
+        .into_iter()
+        .map(|pid| {
+            thread::Builder::new()
+                .name(format!("wait-{}", pid))
+                .spawn(move || -> Result<Instance> {
+                    let waited = ffi::wait_for_pid(pid)?;
+                    let real = waited.ended.saturating_duration_since(start);
+                    Ok(Instance {
+                        pid,
+                        status: waited.status,
+                        real,
+                        user: ffi::timeval_to_duration(waited.usage.ru_utime),
+                        sys: ffi::timeval_to_duration(waited.usage.ru_stime),
+                        max_rss: ffi::max_rss(&waited.usage),
+                    })
+                })
+                .map_err(|e| anyhow!("Failed to spawn thread: {}", e))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut instances = vec![];
+    for waiter in waiters {
+        instances.push(waiter.join().map_err(|_| anyhow!("Wait thread panicked"))??);
+    }
+
+    Ok(instances)
+}
+
+/// Compares each batch against the first.
+pub fn scaling(batches: &[Batch]) -> Vec<Scaling> {
+    let base = match batches.first() {
+        Some(base) => base,
+        None => return vec![],
+    };
+
+    batches
+        .iter()
+        .map(|batch| {
+            let speedup = batch.throughput() / base.throughput();
+            Scaling {
+                jobs: batch.jobs(),
+                wall: batch.wall,
+                throughput: batch.throughput(),
+                speedup,
+                efficiency: speedup * base.jobs() as f64 / batch.jobs() as f64,
+            }
+        })
+        .collect()
+}
+
+/// Parses a list of job counts, e.g.: `1,2,4,8`.
+pub fn parse_scale(s: &str) -> Result<Vec<u32>> {
+    let mut counts = vec![];
+    for part in s.split(',') {
+        let n = part
+            .trim()
+            .parse::<u32>()
+            .map_err(|e| anyhow!("Invalid job count '{}' in '{}': {}", part, s, e))?;
+        if n == 0 {
+            bail!("Invalid job count '{}' in '{}': must be at least 1", part, s);
+        }
+        counts.push(n);
+    }
+
+    Ok(counts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::child::Redirect;
+
+    fn batch(jobs: usize, wall: u64) -> Batch {
+        let instance = Instance {
+            pid: 1,
+            status: 0,
+            real: Duration::from_millis(wall),
+            user: Duration::ZERO,
+            sys: Duration::ZERO,
+            max_rss: 0,
+        };
+        Batch {
+            wall: Duration::from_millis(wall),
+            instances: vec![instance; jobs],
+        }
+    }
+
+    #[test]
+    fn test_parse_scale() {
+        assert_eq!(parse_scale("1,2,4,8").unwrap(), vec![1, 2, 4, 8]);
+        assert_eq!(parse_scale("3").unwrap(), vec![3]);
+        assert!(parse_scale("1,0").is_err());
+        assert!(parse_scale("1,,2").is_err());
+    }
+
+    #[test]
+    fn test_run_shared_stdout() {
+        let out = std::env::temp_dir().join(format!("timers-test-jobs-{}", std::process::id()));
+        let batch = run(
+            4,
+            Command::new("sh")
+                .args(["-c", "echo $$"])
+                .stdout(Redirect::File(out.clone())),
+        )
+        .unwrap();
+        let written = std::fs::read_to_string(&out).unwrap();
+        std::fs::remove_file(&out).unwrap();
+
+        // Every copy's output is kept, rather than each overwriting the last
+        let mut pids = written
+            .lines()
+            .map(|l| l.parse().unwrap())
+            .collect::<Vec<libc::pid_t>>();
+        pids.sort_unstable();
+        let mut expected = batch.instances.iter().map(|i| i.pid).collect::<Vec<_>>();
+        expected.sort_unstable();
+        assert_eq!(pids, expected);
+    }
+
+    #[test]
+    fn test_scaling() {
+        assert!(scaling(&[]).is_empty());
+
+        let rows = scaling(&[batch(2, 1000), batch(4, 1000), batch(8, 4000)]);
+        assert_eq!(rows[0].speedup, 1.0);
+        assert_eq!(rows[0].efficiency, 1.0);
+        assert_eq!(rows[1].throughput, 4.0);
+        assert_eq!(rows[1].speedup, 2.0);
+        assert_eq!(rows[1].efficiency, 1.0);
+        assert_eq!(rows[2].speedup, 1.0);
+        assert_eq!(rows[2].efficiency, 0.25);
+    }
+}
@@ -0,0 +1,48 @@
+//! Measuring how long a command takes and the resources it uses, the library behind the `timers` binary.
+//!
+//! A `Command` is built much like `std::process::Command`, and running it returns a `Measurement` rather than
+//! reporting anything itself:
+//!
+//! ```
+//! # fn main() -> anyhow::Result<()> {
+//! use timers::{Command, Status};
+//!
+//! let measurement = Command::new("sh").args(["-c", "exit 3"]).env("LC_ALL", "C").measure()?;
+//! assert_eq!(measurement.exit(), Some(Status::Exited(3)));
+//! println!("real: {:?}, user: {:?}, max_rss: {}", measurement.real, measurement.user, measurement.max_rss);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Everything else, e.g.: following the command's descendants or sampling its memory use, is private to the binary.
+
+mod app;
+mod attach;
+mod capture;
+mod check;
+mod child;
+mod cli;
+mod clocks;
+mod command;
+mod export;
+mod ffi;
+mod fmt;
+mod jobs;
+mod marker;
+mod measurement;
+mod pipeline;
+mod sample;
+mod status;
+mod trace;
+mod watch;
+
+pub use child::{Environment, Isolation, Limit, Redirect, Resource, Stdio};
+pub use clocks::{Drift, Reading};
+pub use command::{Child, Command};
+pub use ffi::Stopped;
+pub use measurement::Measurement;
+pub use status::{SignalExit, Status};
+
+// Not part of the API, the binary is only `main.rs` so that it can use the private modules
+#[doc(hidden)]
+pub use app::run;
@@ -0,0 +1,124 @@
+//! Lap markers, which the child can write to an inherited file descriptor to record phases of its execution.
+
+use crate::ffi::{cloexec_pipe, cvt};
+use anyhow::{anyhow, Context, Result};
+use nix::unistd::close;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::os::unix::io::{FromRawFd, RawFd};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// The environment variable which tells the child which file descriptor to write markers to.
+pub const MARKER_FD_VAR: &str = "TIMERS_MARKER_FD";
+
+/// A named point in time, written by the child.
+#[derive(Debug, Clone)]
+pub struct Lap {
+    pub name: String,
+    /// Time from the start of the measurement until the marker was read.
+    pub at: Duration,
+    /// The child's user and system time when the marker was read.
+    pub cpu: Option<(Duration, Duration)>,
+}
+
+/// A pipe which is created before forking, the child inherits the write end.
+pub struct Markers {
+    cpu: bool,
+    read: RawFd,
+    write: RawFd,
+}
+
+impl Markers {
+    /// Creates the pipe, if `cpu` is set then the child's CPU times are recorded with each lap.
+    pub fn new(cpu: bool) -> Result<Markers> {
+        let (read, write) = cloexec_pipe()?;
+        Ok(Markers { cpu, read, write })
+    }
+
+    /// The variable which tells the command where to write markers, to add to its environment.
+    pub fn var(&self) -> (&'static str, String) {
+        (MARKER_FD_VAR, self.write.to_string())
+    }
+
+    /// Lets the write end survive `execve`, called in the child.
+    pub fn inherit(&self) -> io::Result<()> {
+        // SAFETY: only changes the flags of our own file descriptor
+        cvt(unsafe { libc::fcntl(self.write, libc::F_SETFD, 0) })?;
+        Ok(())
+    }
+
+    /// Starts reading markers, called in the parent after forking.
+    pub fn start(self, start: Instant, pid: libc::pid_t) -> Result<JoinHandle<io::Result<Vec<Lap>>>> {
+        close(self.write)?;
+
+        // SAFETY: we own the read end of the pipe, and nothing else closes it
+        let file = unsafe { File::from_raw_fd(self.read) };
+        let cpu = self.cpu;
+        let handle = thread::Builder::new()
+            .name("markers".into())
+            .spawn(move || read_laps(file, start, cpu.then_some(pid)))?;
+
+        Ok(handle)
+    }
+}
+
+/// Waits until the marker pipe is closed by the child, and any descendants it was passed on to.
+pub fn join(handle: JoinHandle<io::Result<Vec<Lap>>>) -> Result<Vec<Lap>> {
+    handle
+        .join()
+        .map_err(|_| anyhow!("Marker reader thread panicked"))?
+        .context("Failed reading markers")
+}
+
+fn read_laps(file: impl Read, start: Instant, pid: Option<libc::pid_t>) -> io::Result<Vec<Lap>> {
+    let mut laps = vec![];
+    let mut reader = BufReader::new(file);
+    let mut line = vec![];
+    // Names are whatever the child wrote, which may not be valid UTF-8
+    while reader.read_until(b'\n', &mut line)? > 0 {
+        let at = start.elapsed();
+        let name = String::from_utf8_lossy(&line).trim().to_string();
+        line.clear();
+        if name.is_empty() {
+            continue;
+        }
+
+        laps.push(Lap {
+            name,
+            at,
+            cpu: pid.and_then(cpu_times),
+        });
+    }
+
+    Ok(laps)
+}
+
+#[cfg(target_os = "linux")]
+fn cpu_times(pid: libc::pid_t) -> Option<(Duration, Duration)> {
+    match crate::ffi::proc::Stat::read(pid) {
+        Ok(stat) => Some((stat.utime, stat.stime)),
+        Err(e) => {
+            log::debug!("Failed to read cpu times for lap: {:#}", e);
+            None
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn cpu_times(_: libc::pid_t) -> Option<(Duration, Duration)> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_laps() {
+        let input: &[u8] = b"load\n\n  parse \n\xff\xfebad\nend";
+        let laps = read_laps(input, Instant::now(), None).unwrap();
+        let names = laps.iter().map(|l| l.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(names, vec!["load", "parse", "\u{fffd}\u{fffd}bad", "end"]);
+    }
+}
@@ -0,0 +1,87 @@
+//! What was measured of a command, once it has terminated.
+
+use crate::clocks::{self, Drift};
+use crate::ffi::{self, Stopped, Waited};
+use crate::status::{self, SignalExit, Status};
+use std::time::Duration;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Measurement {
+    pub pid: libc::pid_t,
+    /// The wait status, see `exit` to decode it.
+    pub status: i32,
+    /// From starting the command until it terminated, including any time it spent stopped.
+    pub real: Duration,
+    pub user: Duration,
+    pub sys: Duration,
+    /// How long it spent stopped, e.g.: with Ctrl-Z, and the signals which stopped it.
+    pub stopped: Stopped,
+    /// Peak resident set size in bytes.
+    pub max_rss: u64,
+    pub hard_page_faults: u64,
+    pub soft_page_faults: u64,
+    /// Number of times the filesystem had to perform real IO (doesn't account for caches).
+    pub disk_inputs: u64,
+    pub disk_outputs: u64,
+    pub voluntary_csw: u64,
+    pub involuntary_csw: u64,
+    /// The name of the executable it was last running, truncated to 15 bytes by the kernel.
+    pub comm: Option<String>,
+    /// The system's clocks when the command was started and when it terminated.
+    pub started: clocks::Reading,
+    pub ended: clocks::Reading,
+}
+
+impl Measurement {
+    pub(crate) fn new(
+        pid: libc::pid_t,
+        real: Duration,
+        waited: Waited,
+        started: clocks::Reading,
+        ended: clocks::Reading,
+    ) -> Measurement {
+        let usage = &waited.usage;
+        Measurement {
+            pid,
+            status: waited.status,
+            real,
+            user: ffi::timeval_to_duration(usage.ru_utime),
+            sys: ffi::timeval_to_duration(usage.ru_stime),
+            stopped: waited.stopped,
+            max_rss: ffi::max_rss(usage),
+            hard_page_faults: usage.ru_majflt as u64,
+            soft_page_faults: usage.ru_minflt as u64,
+            disk_inputs: usage.ru_inblock as u64,
+            disk_outputs: usage.ru_oublock as u64,
+            voluntary_csw: usage.ru_nvcsw as u64,
+            involuntary_csw: usage.ru_nivcsw as u64,
+            comm: waited.comm,
+            started,
+            ended,
+        }
+    }
+
+    /// How the command terminated.
+    pub fn exit(&self) -> Option<Status> {
+        Status::decode(self.status)
+    }
+
+    /// The code to exit with to pass on how the command terminated.
+    pub fn exit_code(&self, convention: SignalExit) -> i32 {
+        status::exit_code(self.status, convention)
+    }
+
+    /// Real time, less the time the command spent stopped.
+    pub fn running(&self) -> Duration {
+        self.real.saturating_sub(self.stopped.time)
+    }
+
+    pub fn percent_cpu(&self) -> f64 {
+        100.0 * (self.user + self.sys).as_secs_f64() / self.real.as_secs_f64()
+    }
+
+    /// Whether the system was suspended or its clocks were adjusted while the command ran.
+    pub fn drift(&self) -> Vec<Drift> {
+        clocks::compare(&self.started, &self.ended)
+    }
+}
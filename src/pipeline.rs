@@ -0,0 +1,138 @@
+//! Running the stages of a pipeline, connected by pipes we set up ourselves, so each of them can be timed on its own.
+
+use crate::child::Stdio;
+use crate::command::Command;
+use crate::ffi::{cloexec_pipe, cvt};
+use crate::jobs::{self, Instance};
+use anyhow::{bail, Result};
+use nix::unistd::close;
+use std::ffi::OsString;
+use std::io;
+use std::os::unix::io::RawFd;
+use std::time::{Duration, Instant};
+
+/// Separates the commands of each stage on the command line.
+pub const SEPARATOR: &str = ":::";
+
+/// The pipes between each stage and the next, closed when dropped.
+struct Pipes(Vec<(RawFd, RawFd)>);
+
+impl Pipes {
+    fn new(stages: usize) -> Result<Pipes> {
+        let mut pipes = Pipes(vec![]);
+        for _ in 1..stages {
+            // Each stage only keeps the ends it's connected to once it execs
+            pipes.0.push(cloexec_pipe()?);
+        }
+
+        Ok(pipes)
+    }
+
+    /// Called in a stage after forking, connects its stdin and stdout to the stages before and after it.
+    fn connect(&self, stage: usize) -> io::Result<()> {
+        // SAFETY: dup2 only operates on file descriptors
+        if let Some((read, _)) = stage.checked_sub(1).and_then(|i| self.0.get(i)) {
+            cvt(unsafe { libc::dup2(*read, libc::STDIN_FILENO) })?;
+        }
+        if let Some((_, write)) = self.0.get(stage) {
+            cvt(unsafe { libc::dup2(*write, libc::STDOUT_FILENO) })?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for Pipes {
+    fn drop(&mut self) {
+        for (read, write) in self.0.drain(..) {
+            let _ = close(read);
+            let _ = close(write);
+        }
+    }
+}
+
+/// Splits a command line into the command of each stage, e.g.: `a ::: b ::: c`.
+pub fn split(command_line: &[OsString]) -> Result<Vec<Vec<OsString>>> {
+    let stages = command_line
+        .split(|arg| arg == SEPARATOR)
+        .map(|stage| stage.to_vec())
+        .collect::<Vec<_>>();
+    if stages.iter().any(|stage| stage.is_empty()) {
+        bail!(
+            "every stage of the pipeline needs a command, separated by '{}'",
+            SEPARATOR
+        );
+    }
+
+    Ok(stages)
+}
+
+/// Starts each stage, and waits for all of them to terminate. `stdio` redirects the pipeline's stdin, stdout and
+/// stderr, and is opened once and shared so stages writing to the same file don't truncate each other's output.
+pub fn run(stages: &[Command], stdio: &Stdio) -> Result<Vec<Instance>> {
+    let stdio = stdio.open()?;
+    let pipes = Pipes::new(stages.len())?;
+    let start = Instant::now();
+    let mut pids = vec![];
+    for (stage, command) in stages.iter().enumerate() {
+        let first = stage == 0;
+        let last = stage + 1 == stages.len();
+        let mut child = command.spawn_with(|| {
+            stdio.apply(first, last)?;
+            pipes.connect(stage)
+        })?;
+        child.started()?;
+        pids.push(child.pid());
+    }
+    // Otherwise a stage reading from a pipe would never see the end of its input
+    drop(pipes);
+
+    jobs::wait_all(pids, start)
+}
+
+/// The stage which used the most cpu time. The others spend more of their time waiting on the pipes, for it to read
+/// what they've written or to write what they read.
+pub fn bottleneck(stages: &[Instance]) -> Option<usize> {
+    stages
+        .iter()
+        .enumerate()
+        .max_by_key(|(_, stage)| stage.user + stage.sys)
+        .filter(|(_, stage)| stage.user + stage.sys > Duration::ZERO)
+        .map(|(i, _)| i)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stage(user: u64, sys: u64) -> Instance {
+        Instance {
+            pid: 1,
+            status: 0,
+            real: Duration::from_millis(100),
+            user: Duration::from_millis(user),
+            sys: Duration::from_millis(sys),
+            max_rss: 0,
+        }
+    }
+
+    #[test]
+    fn test_split() {
+        let args = |s: &str| s.split(' ').map(OsString::from).collect::<Vec<_>>();
+        assert_eq!(
+            split(&args("cat file ::: gzip -9 ::: wc -c")).unwrap(),
+            vec![args("cat file"), args("gzip -9"), args("wc -c")]
+        );
+        assert_eq!(split(&args("true")).unwrap(), vec![args("true")]);
+        assert!(split(&args("cat file ::: ::: wc -c")).is_err());
+        assert!(split(&args("cat file :::")).is_err());
+    }
+
+    #[test]
+    fn test_bottleneck() {
+        assert_eq!(bottleneck(&[]), None);
+        assert_eq!(bottleneck(&[stage(0, 0), stage(0, 0)]), None);
+        assert_eq!(bottleneck(&[stage(10, 5), stage(80, 2), stage(1, 1)]), Some(1));
+        assert_eq!(bottleneck(&[stage(10, 50), stage(30, 2)]), Some(0));
+    }
+}
@@ -0,0 +1,285 @@
+//! Sampling the child's resource usage at a regular interval while it runs, to build a timeline.
+
+use anyhow::{anyhow, bail, Context, Result};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// Memory usage within this fraction of the peak counts as being near the peak.
+pub const NEAR_PEAK: f64 = 0.9;
+
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub interval: Duration,
+    /// Where to write the samples, as JSON if it ends with `.json` and CSV otherwise.
+    pub output: Option<PathBuf>,
+}
+
+/// The child's resource usage at a point in time.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Sample {
+    /// Time from the start of the measurement until the sample was taken.
+    pub at: Duration,
+    /// Resident set size in bytes.
+    pub rss: u64,
+    /// User and system time used since the previous sample, as a percentage of the time between them.
+    pub cpu_pct: f64,
+    pub threads: u64,
+    pub fds: usize,
+    /// Bytes read from and written to storage so far, if we're allowed to see them.
+    pub read_bytes: Option<u64>,
+    pub write_bytes: Option<u64>,
+}
+
+/// Metrics derived from a timeline of samples.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Summary {
+    pub avg_cpu_pct: f64,
+    pub peak_rss: u64,
+    /// Time the resident set size was within `NEAR_PEAK` of its peak.
+    pub near_peak: Duration,
+}
+
+/// Samples the child from a background thread until it's stopped.
+pub struct Sampler {
+    stop: Arc<AtomicBool>,
+    handle: JoinHandle<Vec<Sample>>,
+}
+
+impl Sampler {
+    pub fn start(pid: libc::pid_t, start: Instant, interval: Duration) -> Result<Sampler> {
+        let stop = Arc::new(AtomicBool::new(false));
+        let handle = thread::Builder::new().name("sampler".into()).spawn({
+            let stop = stop.clone();
+            move || sample_until(pid, start, interval, &stop)
+        })?;
+
+        Ok(Sampler { stop, handle })
+    }
+
+    /// Stops sampling, called once the child has terminated.
+    pub fn stop(self) -> Result<Vec<Sample>> {
+        self.stop.store(true, Ordering::Relaxed);
+        self.handle.join().map_err(|_| anyhow!("Sampler thread panicked"))
+    }
+}
+
+fn sample_until(pid: libc::pid_t, start: Instant, interval: Duration, stop: &AtomicBool) -> Vec<Sample> {
+    let mut samples = vec![];
+    // The first sample's cpu usage is since the child started
+    let mut previous = (Duration::ZERO, Duration::ZERO);
+    while !stop.load(Ordering::Relaxed) {
+        match sample(pid, start, previous) {
+            Ok((sample, cpu)) => {
+                previous = (sample.at, cpu);
+                samples.push(sample);
+            }
+            // The child has exited, and is a zombie or has been reaped
+            Err(e) => {
+                log::debug!("Stopped sampling: {:#}", e);
+                break;
+            }
+        }
+
+        thread::sleep(interval);
+    }
+
+    samples
+}
+
+/// Takes a sample, returning it with the total cpu time used so far.
+#[cfg(target_os = "linux")]
+fn sample(pid: libc::pid_t, start: Instant, previous: (Duration, Duration)) -> Result<(Sample, Duration)> {
+    use crate::ffi::proc;
+
+    let stat = proc::Stat::read(pid)?;
+    let at = start.elapsed();
+    let status = proc::Status::read(pid)?;
+    let io = proc::Io::read(pid).ok();
+
+    let cpu = stat.utime + stat.stime;
+    let wall = at.saturating_sub(previous.0).as_secs_f64();
+    let sample = Sample {
+        at,
+        rss: status.vm_rss.context("Missing resident set size")?,
+        cpu_pct: if wall > 0.0 {
+            100.0 * cpu.saturating_sub(previous.1).as_secs_f64() / wall
+        } else {
+            0.0
+        },
+        threads: status.threads,
+        fds: proc::fd_count(pid)?,
+        read_bytes: io.as_ref().map(|io| io.read_bytes),
+        write_bytes: io.as_ref().map(|io| io.write_bytes),
+    };
+
+    Ok((sample, cpu))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn sample(_: libc::pid_t, _: Instant, _: (Duration, Duration)) -> Result<(Sample, Duration)> {
+    bail!("Sampling is only supported on Linux")
+}
+
+/// Parses an interval like `10ms` or `1s`, a number without a suffix is in milliseconds.
+pub fn parse_interval(s: &str) -> Result<Duration> {
+    // Suffixes are tried in order, so `ms` has to come before `s`
+    let millis = crate::fmt::parse_suffixed(s, &[("ms", 1), ("s", 1000)])?;
+    if millis == 0 {
+        bail!("'{}': interval must be at least 1ms", s);
+    }
+
+    Ok(Duration::from_millis(millis))
+}
+
+/// Returns `None` if there are no samples.
+pub fn summarise(samples: &[Sample]) -> Option<Summary> {
+    let last = samples.last()?;
+    let peak_rss = samples.iter().map(|s| s.rss).max()?;
+
+    // Each sample stands for the time since the one before it
+    let mut near_peak = Duration::ZERO;
+    let mut cpu = 0.0;
+    let mut previous = Duration::ZERO;
+    for sample in samples {
+        let elapsed = sample.at.saturating_sub(previous);
+        if sample.rss as f64 >= peak_rss as f64 * NEAR_PEAK {
+            near_peak += elapsed;
+        }
+        cpu += sample.cpu_pct * elapsed.as_secs_f64();
+        previous = sample.at;
+    }
+
+    Some(Summary {
+        avg_cpu_pct: if last.at.is_zero() {
+            0.0
+        } else {
+            cpu / last.at.as_secs_f64()
+        },
+        peak_rss,
+        near_peak,
+    })
+}
+
+/// Writes the samples to a file, as JSON if its name ends with `.json` and CSV otherwise.
+pub fn write(path: &Path, samples: &[Sample]) -> Result<()> {
+    let file = File::create(path).with_context(|| format!("Failed to create {}", path.display()))?;
+    let mut out = BufWriter::new(file);
+    if path.extension().is_some_and(|e| e == "json") {
+        write_json(&mut out, samples)?;
+    } else {
+        write_csv(&mut out, samples)?;
+    }
+
+    out.flush()
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+fn write_csv(out: &mut impl Write, samples: &[Sample]) -> Result<()> {
+    let opt = |n: Option<u64>| n.map_or(String::new(), |n| n.to_string());
+    writeln!(out, "at_ns,rss_bytes,cpu_percent,threads,fds,read_bytes,write_bytes")?;
+    for s in samples {
+        writeln!(
+            out,
+            "{},{},{:.2},{},{},{},{}",
+            s.at.as_nanos(),
+            s.rss,
+            s.cpu_pct,
+            s.threads,
+            s.fds,
+            opt(s.read_bytes),
+            opt(s.write_bytes)
+        )?;
+    }
+
+    Ok(())
+}
+
+fn write_json(out: &mut impl Write, samples: &[Sample]) -> Result<()> {
+    let opt = |n: Option<u64>| n.map_or("null".into(), |n| n.to_string());
+    writeln!(out, "[")?;
+    for (i, s) in samples.iter().enumerate() {
+        writeln!(
+            out,
+            r#"  {{"at_ns": {}, "rss_bytes": {}, "cpu_percent": {:.2}, "threads": {}, "fds": {}, "read_bytes": {}, "write_bytes": {}}}{}"#,
+            s.at.as_nanos(),
+            s.rss,
+            s.cpu_pct,
+            s.threads,
+            s.fds,
+            opt(s.read_bytes),
+            opt(s.write_bytes),
+            if i + 1 < samples.len() { "," } else { "" }
+        )?;
+    }
+    writeln!(out, "]")?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(at: u64, rss: u64, cpu_pct: f64) -> Sample {
+        Sample {
+            at: Duration::from_millis(at),
+            rss,
+            cpu_pct,
+            threads: 1,
+            fds: 3,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_parse_interval() {
+        assert_eq!(parse_interval("10ms").unwrap(), Duration::from_millis(10));
+        assert_eq!(parse_interval("2s").unwrap(), Duration::from_secs(2));
+        assert_eq!(parse_interval("250").unwrap(), Duration::from_millis(250));
+        assert!(parse_interval("0ms").is_err());
+        assert!(parse_interval("fast").is_err());
+    }
+
+    #[test]
+    fn test_summarise() {
+        assert_eq!(summarise(&[]), None);
+
+        let samples = [
+            sample(10, 50, 100.0),
+            sample(20, 95, 50.0),
+            sample(40, 100, 0.0),
+            sample(50, 10, 100.0),
+        ];
+        let summary = summarise(&samples).unwrap();
+        assert_eq!(summary.peak_rss, 100);
+        assert_eq!(summary.near_peak, Duration::from_millis(30));
+        assert!((summary.avg_cpu_pct - 50.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_write_csv() {
+        let mut samples = vec![sample(10, 4096, 12.345)];
+        samples[0].read_bytes = Some(1);
+        let mut out = vec![];
+        write_csv(&mut out, &samples).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "at_ns,rss_bytes,cpu_percent,threads,fds,read_bytes,write_bytes\n10000000,4096,12.35,1,3,1,\n"
+        );
+    }
+
+    #[test]
+    fn test_write_json() {
+        let mut out = vec![];
+        write_json(&mut out, &[sample(10, 4096, 0.0), sample(20, 8192, 100.0)]).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.starts_with("[\n  {\"at_ns\": 10000000, \"rss_bytes\": 4096, \"cpu_percent\": 0.00,"));
+        assert!(out.contains("\"read_bytes\": null, \"write_bytes\": null},\n"));
+        assert!(out.ends_with("\"write_bytes\": null}\n]\n"));
+    }
+}
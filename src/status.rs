@@ -0,0 +1,349 @@
+//! Decoding the status returned by `wait4`, and describing signals without relying on the platform's `strsignal`.
+
+use anyhow::{bail, Result};
+use std::fmt;
+use std::path::Path;
+
+/// What happens to a process when it receives a signal it hasn't set a handler for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Terminate,
+    /// Terminate and dump core.
+    Core,
+    Ignore,
+    Stop,
+    Continue,
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Action::Terminate => "terminate",
+            Action::Core => "terminate and dump core",
+            Action::Ignore => "ignore",
+            Action::Stop => "stop",
+            Action::Continue => "continue",
+        })
+    }
+}
+
+/// Signals by name, their numbers differ between platforms. See `man 7 signal`.
+const SIGNALS: &[(libc::c_int, &str, Action)] = &[
+    (libc::SIGHUP, "SIGHUP", Action::Terminate),
+    (libc::SIGINT, "SIGINT", Action::Terminate),
+    (libc::SIGQUIT, "SIGQUIT", Action::Core),
+    (libc::SIGILL, "SIGILL", Action::Core),
+    (libc::SIGTRAP, "SIGTRAP", Action::Core),
+    (libc::SIGABRT, "SIGABRT", Action::Core),
+    (libc::SIGBUS, "SIGBUS", Action::Core),
+    (libc::SIGFPE, "SIGFPE", Action::Core),
+    (libc::SIGKILL, "SIGKILL", Action::Terminate),
+    (libc::SIGUSR1, "SIGUSR1", Action::Terminate),
+    (libc::SIGSEGV, "SIGSEGV", Action::Core),
+    (libc::SIGUSR2, "SIGUSR2", Action::Terminate),
+    (libc::SIGPIPE, "SIGPIPE", Action::Terminate),
+    (libc::SIGALRM, "SIGALRM", Action::Terminate),
+    (libc::SIGTERM, "SIGTERM", Action::Terminate),
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    (libc::SIGSTKFLT, "SIGSTKFLT", Action::Terminate),
+    (libc::SIGCHLD, "SIGCHLD", Action::Ignore),
+    (libc::SIGCONT, "SIGCONT", Action::Continue),
+    (libc::SIGSTOP, "SIGSTOP", Action::Stop),
+    (libc::SIGTSTP, "SIGTSTP", Action::Stop),
+    (libc::SIGTTIN, "SIGTTIN", Action::Stop),
+    (libc::SIGTTOU, "SIGTTOU", Action::Stop),
+    (libc::SIGURG, "SIGURG", Action::Ignore),
+    (libc::SIGXCPU, "SIGXCPU", Action::Core),
+    (libc::SIGXFSZ, "SIGXFSZ", Action::Core),
+    (libc::SIGVTALRM, "SIGVTALRM", Action::Terminate),
+    (libc::SIGPROF, "SIGPROF", Action::Terminate),
+    (libc::SIGWINCH, "SIGWINCH", Action::Ignore),
+    (libc::SIGIO, "SIGIO", Action::Terminate),
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    (libc::SIGPWR, "SIGPWR", Action::Terminate),
+    (libc::SIGSYS, "SIGSYS", Action::Core),
+    #[cfg(target_os = "macos")]
+    (libc::SIGEMT, "SIGEMT", Action::Core),
+    #[cfg(target_os = "macos")]
+    (libc::SIGINFO, "SIGINFO", Action::Ignore),
+];
+
+/// Returns the name of a signal, e.g.: `SIGSEGV`, or `None` if it isn't known.
+pub fn signal_name(signal: libc::c_int) -> Option<String> {
+    if let Some((_, name, _)) = SIGNALS.iter().find(|(n, _, _)| *n == signal) {
+        return Some(name.to_string());
+    }
+    #[cfg(target_os = "linux")]
+    if (libc::SIGRTMIN()..=libc::SIGRTMAX()).contains(&signal) {
+        return Some(format!("SIGRTMIN+{}", signal - libc::SIGRTMIN()));
+    }
+
+    None
+}
+
+/// Returns what a signal does by default, or `None` if it isn't known.
+pub fn default_action(signal: libc::c_int) -> Option<Action> {
+    if let Some((_, _, action)) = SIGNALS.iter().find(|(n, _, _)| *n == signal) {
+        return Some(*action);
+    }
+    // Real-time signals all terminate the process
+    #[cfg(target_os = "linux")]
+    if (libc::SIGRTMIN()..=libc::SIGRTMAX()).contains(&signal) {
+        return Some(Action::Terminate);
+    }
+
+    None
+}
+
+/// Describes a signal by its name and number, e.g.: `SIGSEGV (11)`.
+pub fn describe_signal(signal: libc::c_int) -> String {
+    match signal_name(signal) {
+        Some(name) => format!("{} ({})", name, signal),
+        None => format!("signal {}", signal),
+    }
+}
+
+/// A status returned by `wait4`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Exited(i32),
+    Signaled { signal: i32, core_dumped: bool },
+    Stopped(i32),
+    Continued,
+}
+
+impl Status {
+    pub fn decode(status: i32) -> Option<Status> {
+        if libc::WIFEXITED(status) {
+            Some(Status::Exited(libc::WEXITSTATUS(status)))
+        } else if libc::WIFSIGNALED(status) {
+            Some(Status::Signaled {
+                signal: libc::WTERMSIG(status),
+                core_dumped: libc::WCOREDUMP(status),
+            })
+        } else if libc::WIFSTOPPED(status) {
+            Some(Status::Stopped(libc::WSTOPSIG(status)))
+        } else if libc::WIFCONTINUED(status) {
+            Some(Status::Continued)
+        } else {
+            None
+        }
+    }
+}
+
+/// How to turn the signal which killed the command into our exit code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SignalExit {
+    /// The signal number itself, which can't be told apart from the command exiting with that code.
+    #[default]
+    Raw,
+    /// 128 plus the signal number, like a shell.
+    Shell,
+}
+
+impl SignalExit {
+    pub fn parse(s: &str) -> Result<SignalExit> {
+        match s {
+            "raw" | "n" => Ok(SignalExit::Raw),
+            "128+n" | "shell" => Ok(SignalExit::Shell),
+            _ => bail!(
+                "Unrecognised signal exit convention: '{}', expected 'raw' or '128+n'",
+                s
+            ),
+        }
+    }
+}
+
+/// The exit code to mirror a terminated process with.
+pub fn exit_code(status: i32, convention: SignalExit) -> i32 {
+    match Status::decode(status) {
+        Some(Status::Exited(code)) => code,
+        Some(Status::Signaled { signal, .. }) => match convention {
+            SignalExit::Raw => signal,
+            SignalExit::Shell => 128 + signal,
+        },
+        _ => 1,
+    }
+}
+
+/// What's known about a process when it dumped core, to fill in the kernel's `core_pattern`.
+pub struct Dumped<'a> {
+    pub pid: libc::pid_t,
+    pub signal: i32,
+    /// The name of the executable, which the kernel truncates to 15 bytes.
+    pub comm: &'a str,
+    /// The directory the process was in, which a relative pattern is relative to.
+    pub cwd: &'a Path,
+}
+
+/// The name the kernel gives a process running `program`, its file name truncated to 15 bytes. Unlike the kernel, a
+/// character is never split.
+pub fn comm(program: &str) -> String {
+    let name = program.rsplit('/').next().unwrap_or_default();
+    name.char_indices()
+        .take_while(|(i, c)| i + c.len_utf8() <= 15)
+        .map(|(_, c)| c)
+        .collect()
+}
+
+/// Where the kernel put a core dump, from the `core_pattern` and `core_uses_pid` sysctls.
+/// Specifiers we can't know the value of (e.g.: `%t`) are left in.
+pub fn core_location(pattern: &str, uses_pid: bool, dumped: &Dumped) -> String {
+    let pattern = pattern.trim_end();
+    if let Some(program) = pattern.strip_prefix('|') {
+        let program = program.split_whitespace().next().unwrap_or_default();
+        return if program.ends_with("systemd-coredump") {
+            format!("sent to systemd-coredump, see `coredumpctl info {}`", dumped.pid)
+        } else {
+            format!("sent to {}", program)
+        };
+    }
+
+    let mut path = String::new();
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            path.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('%') => path.push('%'),
+            Some('p') | Some('P') | Some('i') | Some('I') => path.push_str(&dumped.pid.to_string()),
+            Some('s') => path.push_str(&dumped.signal.to_string()),
+            Some('e') => path.push_str(dumped.comm),
+            // SAFETY: getuid and getgid can't fail
+            Some('u') => path.push_str(&unsafe { libc::getuid() }.to_string()),
+            Some('g') => path.push_str(&unsafe { libc::getgid() }.to_string()),
+            Some('h') => path.push_str(
+                &nix::unistd::gethostname(&mut [0; 256]).map_or("%h".into(), |h| h.to_string_lossy().into_owned()),
+            ),
+            Some(other) => {
+                path.push('%');
+                path.push(other);
+            }
+            None => {}
+        }
+    }
+    if uses_pid && !pattern.contains("%p") {
+        path.push_str(&format!(".{}", dumped.pid));
+    }
+
+    if path.starts_with('/') {
+        path
+    } else {
+        dumped.cwd.join(path).display().to_string()
+    }
+}
+
+/// Reads the kernel's settings for where core dumps go, and returns where this one went.
+#[cfg(target_os = "linux")]
+pub fn core_file(dumped: &Dumped) -> Result<String> {
+    use anyhow::Context;
+    use std::fs;
+
+    let pattern = fs::read_to_string("/proc/sys/kernel/core_pattern").context("Failed to read core_pattern")?;
+    let uses_pid = fs::read_to_string("/proc/sys/kernel/core_uses_pid").is_ok_and(|s| s.trim() == "1");
+    Ok(core_location(&pattern, uses_pid, dumped))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn core_file(_: &Dumped) -> Result<String> {
+    bail!("Finding core dumps is only supported on Linux")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dumped() -> Dumped<'static> {
+        Dumped {
+            pid: 42,
+            signal: libc::SIGSEGV,
+            comm: "crashy",
+            cwd: Path::new("/home/me/project"),
+        }
+    }
+
+    #[test]
+    fn test_comm() {
+        assert_eq!(comm("/usr/bin/crashy"), "crashy");
+        assert_eq!(comm("a-very-long-program-name"), "a-very-long-pro");
+        assert_eq!(comm("./éééééééééé"), "ééééééé");
+    }
+
+    #[test]
+    fn test_signal_name() {
+        assert_eq!(signal_name(libc::SIGSEGV).as_deref(), Some("SIGSEGV"));
+        assert_eq!(signal_name(libc::SIGKILL).as_deref(), Some("SIGKILL"));
+        assert_eq!(signal_name(0), None);
+        #[cfg(target_os = "linux")]
+        assert_eq!(signal_name(libc::SIGRTMIN() + 2).as_deref(), Some("SIGRTMIN+2"));
+        assert_eq!(describe_signal(libc::SIGINT), format!("SIGINT ({})", libc::SIGINT));
+    }
+
+    #[test]
+    fn test_default_action() {
+        assert_eq!(default_action(libc::SIGSEGV), Some(Action::Core));
+        assert_eq!(default_action(libc::SIGTERM), Some(Action::Terminate));
+        assert_eq!(default_action(libc::SIGTSTP), Some(Action::Stop));
+        assert_eq!(default_action(libc::SIGCHLD), Some(Action::Ignore));
+        assert_eq!(default_action(0), None);
+    }
+
+    #[test]
+    fn test_decode() {
+        // Status words are laid out the same way on Linux and macOS
+        assert_eq!(Status::decode(3 << 8), Some(Status::Exited(3)));
+        assert_eq!(
+            Status::decode(libc::SIGSEGV | 0x80),
+            Some(Status::Signaled {
+                signal: libc::SIGSEGV,
+                core_dumped: true
+            })
+        );
+        assert_eq!(
+            Status::decode(libc::SIGKILL),
+            Some(Status::Signaled {
+                signal: libc::SIGKILL,
+                core_dumped: false
+            })
+        );
+        assert_eq!(
+            Status::decode((libc::SIGTSTP << 8) | 0x7f),
+            Some(Status::Stopped(libc::SIGTSTP))
+        );
+    }
+
+    #[test]
+    fn test_exit_code() {
+        assert_eq!(exit_code(3 << 8, SignalExit::Shell), 3);
+        assert_eq!(exit_code(libc::SIGKILL, SignalExit::Raw), libc::SIGKILL);
+        assert_eq!(exit_code(libc::SIGKILL, SignalExit::Shell), 128 + libc::SIGKILL);
+        assert_eq!(SignalExit::parse("128+n").unwrap(), SignalExit::Shell);
+        assert!(SignalExit::parse("256").is_err());
+    }
+
+    #[test]
+    fn test_core_location() {
+        let dumped = dumped();
+        assert_eq!(core_location("core\n", false, &dumped), "/home/me/project/core");
+        assert_eq!(core_location("core", true, &dumped), "/home/me/project/core.42");
+        assert_eq!(
+            core_location("/var/crash/%e.%p.%s.%t", true, &dumped),
+            "/var/crash/crashy.42.11.%t"
+        );
+        assert_eq!(core_location("/tmp/100%%", false, &dumped), "/tmp/100%");
+        assert_eq!(
+            core_location(
+                "|/usr/lib/systemd/systemd-coredump %P %u %g %s %t %c %h",
+                false,
+                &dumped
+            ),
+            "sent to systemd-coredump, see `coredumpctl info 42`"
+        );
+        assert_eq!(
+            core_location("|/usr/share/apport/apport -p%p -s%s", false, &dumped),
+            "sent to /usr/share/apport/apport"
+        );
+    }
+}
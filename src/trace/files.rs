@@ -0,0 +1,242 @@
+use super::File;
+use crate::ffi::ptrace;
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::fs;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+/// A system call which touches a file, and the path it was given if it takes one.
+struct Pending {
+    name: &'static str,
+    args: [u64; 6],
+    path: Option<PathBuf>,
+}
+
+/// Accounts for the files used by every process, either from their system calls or by sampling their open files.
+#[derive(Default)]
+pub struct Files {
+    /// The path of each file descriptor a process has used, keyed by the process and the descriptor.
+    fds: HashMap<(libc::pid_t, i32), Option<PathBuf>>,
+    /// The call each task is in, keyed by task.
+    pending: HashMap<libc::pid_t, Pending>,
+    files: HashMap<PathBuf, File>,
+}
+
+impl Files {
+    /// Called when a task enters a system call, paths are read now since they may be gone once it returns.
+    pub fn on_entry(&mut self, tid: libc::pid_t, pid: libc::pid_t, name: &'static str, args: [u64; 6]) {
+        let path = match name {
+            "open" | "creat" | "stat" | "lstat" | "access" | "execve" => self.path(tid, pid, libc::AT_FDCWD, args[0]),
+            "openat" | "openat2" | "newfstatat" | "statx" | "faccessat" | "faccessat2" | "execveat" => {
+                self.path(tid, pid, args[0] as i32, args[1])
+            }
+            "read" | "pread64" | "readv" | "preadv" | "preadv2" | "write" | "pwrite64" | "writev" | "pwritev"
+            | "pwritev2" | "sendfile" | "copy_file_range" | "fstat" | "close" | "dup2" | "dup3" | "close_range" => None,
+            _ => return,
+        };
+
+        // An exec doesn't return if it succeeds, so it's counted here
+        if name.starts_with("execve") {
+            if let Some(path) = &path {
+                self.file(path).execs += 1;
+            }
+        }
+        self.pending.insert(tid, Pending { name, args, path });
+    }
+
+    /// Called when a task leaves a system call.
+    pub fn on_exit(&mut self, tid: libc::pid_t, pid: libc::pid_t, rval: i64, is_error: bool) {
+        let Pending { name, args, path } = match self.pending.remove(&tid) {
+            Some(pending) => pending,
+            None => return,
+        };
+        let fd = |n: usize| args[n] as i32;
+
+        match name {
+            "open" | "creat" | "openat" | "openat2" => {
+                if is_error {
+                    if let Some(path) = path {
+                        self.file(&path).errors += 1;
+                    }
+                    return;
+                }
+                // The kernel's view of the path is canonical, so prefer it to the one we were given
+                self.fds.remove(&(pid, rval as i32));
+                if let Some(path) = self.fd_path(pid, rval as i32).or(path) {
+                    self.file(&path).opens += 1;
+                }
+            }
+            "stat" | "lstat" | "access" | "newfstatat" | "statx" | "faccessat" | "faccessat2" => {
+                if let Some(path) = path {
+                    let file = self.file(&path);
+                    file.stats += 1;
+                    file.errors += is_error as u64;
+                }
+            }
+            "fstat" if !is_error => {
+                if let Some(path) = self.fd_path(pid, fd(0)) {
+                    self.file(&path).stats += 1;
+                }
+            }
+            "execve" | "execveat" if is_error => {
+                if let Some(path) = path {
+                    let file = self.file(&path);
+                    file.execs -= 1;
+                    file.errors += 1;
+                }
+            }
+            "read" | "pread64" | "readv" | "preadv" | "preadv2" if rval > 0 => {
+                self.add_bytes(pid, fd(0), rval as u64, 0)
+            }
+            "write" | "pwrite64" | "writev" | "pwritev" | "pwritev2" if rval > 0 => {
+                self.add_bytes(pid, fd(0), 0, rval as u64)
+            }
+            "sendfile" if rval > 0 => {
+                self.add_bytes(pid, fd(1), rval as u64, 0);
+                self.add_bytes(pid, fd(0), 0, rval as u64);
+            }
+            "copy_file_range" if rval > 0 => {
+                self.add_bytes(pid, fd(0), rval as u64, 0);
+                self.add_bytes(pid, fd(2), 0, rval as u64);
+            }
+            "close" => {
+                self.fds.remove(&(pid, fd(0)));
+            }
+            // The new descriptor replaces whatever it was before
+            "dup2" | "dup3" => {
+                self.fds.remove(&(pid, fd(1)));
+            }
+            "close_range" => self.forget(pid),
+            _ => {}
+        }
+    }
+
+    /// Called when a process execs, which closes some of its descriptors. The exec may have been called by a thread
+    /// which has since taken over the leader's pid.
+    pub fn on_exec(&mut self, pid: libc::pid_t, former: libc::pid_t) {
+        self.forget(pid);
+        if let Some(pending) = self.pending.remove(&former) {
+            self.pending.insert(pid, pending);
+        }
+    }
+
+    /// Called when the command has been exec'd. That exec was part of our setup so its system call wasn't seen, and
+    /// the executable is read back instead.
+    pub fn on_started(&mut self, pid: libc::pid_t) {
+        if let Ok(path) = fs::read_link(format!("/proc/{}/exe", pid)) {
+            self.file(&path).execs += 1;
+        }
+    }
+
+    /// Called when a task has terminated.
+    pub fn on_gone(&mut self, tid: libc::pid_t, pid: libc::pid_t) {
+        self.pending.remove(&tid);
+        if tid == pid {
+            self.forget(pid);
+        }
+    }
+
+    /// Records every file the process currently has open, used when we can't see its system calls.
+    pub fn sample(&mut self, pid: libc::pid_t) {
+        let entries = match fs::read_dir(format!("/proc/{}/fd", pid)) {
+            Ok(entries) => entries,
+            Err(_) => return,
+        };
+
+        for entry in entries.flatten() {
+            let fd = match entry.file_name().to_str().and_then(|name| name.parse().ok()) {
+                Some(fd) => fd,
+                None => continue,
+            };
+            let path = match fs::read_link(entry.path()) {
+                Ok(path) if path.is_absolute() => path,
+                _ => continue,
+            };
+            // A descriptor we haven't seen, or one that's been reused for another file
+            if self.fds.get(&(pid, fd)).and_then(|p| p.as_ref()) != Some(&path) {
+                self.file(&path).opens += 1;
+                self.fds.insert((pid, fd), Some(path));
+            }
+        }
+    }
+
+    /// Forgets the descriptors of a process, since they may be reused for other files.
+    fn forget(&mut self, pid: libc::pid_t) {
+        self.fds.retain(|(p, _), _| *p != pid);
+    }
+
+    /// Returns every file seen, in order of their paths. Bytes are only known if we saw the system calls.
+    pub fn into_files(self, with_bytes: bool) -> Vec<File> {
+        let mut files = self
+            .files
+            .into_values()
+            .map(|mut file| {
+                if !with_bytes {
+                    file.read = None;
+                    file.written = None;
+                }
+                file
+            })
+            .collect::<Vec<_>>();
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+        files
+    }
+
+    fn file(&mut self, path: &Path) -> &mut File {
+        self.files.entry(path.to_path_buf()).or_insert_with(|| File {
+            path: path.to_path_buf(),
+            read: Some(0),
+            written: Some(0),
+            ..Default::default()
+        })
+    }
+
+    fn add_bytes(&mut self, pid: libc::pid_t, fd: i32, read: u64, written: u64) {
+        if let Some(path) = self.fd_path(pid, fd) {
+            let file = self.file(&path);
+            file.read = file.read.map(|n| n + read);
+            file.written = file.written.map(|n| n + written);
+        }
+    }
+
+    /// Returns the path of an open file descriptor, or `None` if it isn't a file (e.g.: a pipe or socket).
+    fn fd_path(&mut self, pid: libc::pid_t, fd: i32) -> Option<PathBuf> {
+        self.fds
+            .entry((pid, fd))
+            .or_insert_with(|| {
+                fs::read_link(format!("/proc/{}/fd/{}", pid, fd))
+                    .ok()
+                    .filter(|p| p.is_absolute())
+            })
+            .clone()
+    }
+
+    /// Reads a path argument from the task, making it absolute if it's relative to `dirfd`.
+    fn path(&mut self, tid: libc::pid_t, pid: libc::pid_t, dirfd: i32, addr: u64) -> Option<PathBuf> {
+        let path = match ptrace::read_string(tid, addr) {
+            Ok(path) => PathBuf::from(OsStr::from_bytes(&path)),
+            Err(e) => {
+                log::debug!("Failed to read path from {}: {:#}", tid, e);
+                return None;
+            }
+        };
+        if path.is_absolute() {
+            return Some(path);
+        }
+
+        let dir = if dirfd == libc::AT_FDCWD {
+            fs::read_link(format!("/proc/{}/cwd", tid)).ok()
+        } else {
+            self.fd_path(pid, dirfd)
+        };
+        // An empty path refers to `dirfd` itself, e.g.: with AT_EMPTY_PATH
+        Some(dir.map_or(path.clone(), |dir| {
+            if path.as_os_str().is_empty() {
+                dir
+            } else {
+                dir.join(path)
+            }
+        }))
+    }
+}
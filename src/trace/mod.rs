@@ -0,0 +1,358 @@
+//! Following the child's descendants, to account for the resources used by each of them.
+
+#[cfg(target_os = "linux")]
+mod files;
+#[cfg(target_os = "linux")]
+mod poll;
+#[cfg(target_os = "linux")]
+mod tracer;
+
+use crate::ffi;
+use anyhow::Result;
+use nix::unistd::close;
+use std::os::unix::io::RawFd;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+/// A process started by the command, or the command itself.
+#[derive(Debug, Clone)]
+pub struct Process {
+    pub pid: libc::pid_t,
+    /// The process which started this one, `None` for the command itself.
+    pub parent: Option<libc::pid_t>,
+    pub argv: Vec<String>,
+    /// Time from the start of the measurement until the process started.
+    pub start: Duration,
+    /// Time from the start of the measurement until the process exited, `None` if it outlived the command.
+    pub end: Option<Duration>,
+    pub user: Duration,
+    pub sys: Duration,
+    /// Peak resident set size in bytes.
+    pub max_rss: Option<u64>,
+}
+
+/// Time spent in one system call, summed over every call made by the command and its descendants.
+#[derive(Debug, Clone, Default)]
+pub struct Syscall {
+    pub nr: u64,
+    pub calls: u64,
+    /// Calls which returned an error.
+    pub errors: u64,
+    /// Time between entering and leaving the call, which includes the overhead of stopping for ptrace twice.
+    pub time: Duration,
+}
+
+impl Syscall {
+    pub fn name(&self) -> String {
+        #[cfg(target_os = "linux")]
+        if let Some(name) = crate::ffi::syscalls::name(self.nr) {
+            return name.into();
+        }
+        format!("syscall_{}", self.nr)
+    }
+}
+
+/// A file used by the command or its descendants.
+#[derive(Debug, Clone, Default)]
+pub struct File {
+    pub path: PathBuf,
+    pub opens: u64,
+    /// Calls to stat or access the file, without opening it.
+    pub stats: u64,
+    pub execs: u64,
+    /// Opens, stats and execs which failed, e.g.: because the file didn't exist.
+    pub errors: u64,
+    /// Bytes read from and written to the file, `None` if we couldn't see the system calls.
+    pub read: Option<u64>,
+    pub written: Option<u64>,
+}
+
+/// How descendants were followed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Method {
+    /// Every process is seen, and is stopped just before it exits to read its final resource usage.
+    Ptrace,
+    /// Processes which start and exit between polls aren't seen, and usage is from the last poll.
+    Poll,
+}
+
+#[derive(Debug)]
+pub struct Tree {
+    pub method: Method,
+    /// Every process that was seen, in the order they started.
+    pub processes: Vec<Process>,
+}
+
+impl Tree {
+    fn new(method: Method, mut processes: Vec<Process>) -> Tree {
+        processes.sort_by_key(|p| (p.start, p.pid));
+        Tree { method, processes }
+    }
+
+    /// Returns each process after its parent, along with how deep it is in the tree.
+    pub fn walk(&self) -> Vec<(usize, &Process)> {
+        let known = |pid| self.processes.iter().any(|p| p.pid == pid);
+        let mut stack = self
+            .processes
+            .iter()
+            .rev()
+            .filter(|p| !p.parent.is_some_and(known))
+            .map(|p| (0, p))
+            .collect::<Vec<_>>();
+
+        let mut walked = vec![];
+        while let Some((depth, process)) = stack.pop() {
+            walked.push((depth, process));
+            let children = self.processes.iter().rev().filter(|p| p.parent == Some(process.pid));
+            stack.extend(children.map(|p| (depth + 1, p)));
+        }
+
+        walked
+    }
+}
+
+/// What to record while following the child, beyond the processes it starts.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Options {
+    /// Stop at every system call, this requires ptrace.
+    pub syscalls: bool,
+    /// Record the files that are used, from system calls if we can trace them, otherwise by sampling open files.
+    pub files: bool,
+}
+
+/// Everything seen while following the child.
+#[derive(Debug)]
+pub struct Trace {
+    pub tree: Tree,
+    /// Sorted by the time spent in each call, most first.
+    pub syscalls: Option<Vec<Syscall>>,
+    /// Sorted by path.
+    pub files: Option<Vec<File>>,
+}
+
+/// Holds the child back after forking, until we've started following it.
+pub struct Gate {
+    read: RawFd,
+    write: RawFd,
+}
+
+impl Gate {
+    pub fn new() -> Result<Gate> {
+        let (read, write) = ffi::cloexec_pipe()?;
+        Ok(Gate { read, write })
+    }
+
+    /// Blocks until the parent opens the gate, called in the child.
+    pub fn wait(&self) -> std::io::Result<()> {
+        use crate::ffi::cvt;
+
+        // SAFETY: only our own file descriptors are closed and read from
+        cvt(unsafe { libc::close(self.write) })?;
+        // Nothing is ever written, the parent closing its end is the signal to continue
+        while let Err(e) = cvt(unsafe { libc::read(self.read, [0u8].as_mut_ptr() as *mut libc::c_void, 1) } as _) {
+            if e.kind() != std::io::ErrorKind::Interrupted {
+                return Err(e);
+            }
+        }
+        cvt(unsafe { libc::close(self.read) })?;
+        Ok(())
+    }
+
+    /// Lets the child continue, called in the parent.
+    pub fn open(self) -> Result<()> {
+        close(self.read)?;
+        close(self.write)?;
+        Ok(())
+    }
+}
+
+/// Follows the child and its descendants while it runs.
+pub enum Tracker {
+    #[cfg(target_os = "linux")]
+    Ptrace(Box<tracer::Tracer>),
+    /// Polling procfs, because attaching with ptrace failed for this reason.
+    #[cfg(target_os = "linux")]
+    Poll(poll::Poller, anyhow::Error),
+}
+
+impl Tracker {
+    /// Starts following the child, which must not have started running the command yet.
+    /// Uses ptrace if we're allowed to, otherwise falls back to polling procfs if none of the options need ptrace.
+    #[cfg(target_os = "linux")]
+    pub fn attach(pid: libc::pid_t, start: Instant, options: Options) -> Result<Tracker> {
+        match tracer::Tracer::attach(pid, start, options) {
+            Ok(tracer) => Ok(Tracker::Ptrace(Box::new(tracer))),
+            Err(e) if options.syscalls => Err(e.context("Tracing system calls requires ptrace")),
+            Err(e) => Ok(Tracker::Poll(poll::Poller::start(pid, start, options)?, e)),
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn attach(_: libc::pid_t, _: Instant, _: Options) -> Result<Tracker> {
+        anyhow::bail!("Following descendants is only supported on Linux")
+    }
+
+    /// Why ptrace couldn't be used, if the tracker fell back to polling procfs.
+    pub fn fallback(&self) -> Option<&anyhow::Error> {
+        match *self {
+            #[cfg(target_os = "linux")]
+            Tracker::Poll(_, ref e) => Some(e),
+            #[cfg(target_os = "linux")]
+            Tracker::Ptrace(_) => None,
+        }
+    }
+
+    /// Waits for the child to terminate, returning what was seen of it and its descendants.
+    pub fn wait(self) -> Result<(crate::ffi::Waited, Trace)> {
+        match self {
+            #[cfg(target_os = "linux")]
+            Tracker::Ptrace(tracer) => tracer.wait(),
+            #[cfg(target_os = "linux")]
+            Tracker::Poll(poller, _) => {
+                let waited = crate::ffi::wait_for_pid(poller.pid())?;
+                Ok((waited, poller.stop()?))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn process(pid: libc::pid_t, parent: Option<libc::pid_t>, start: u64) -> Process {
+        Process {
+            pid,
+            parent,
+            argv: vec![],
+            start: Duration::from_millis(start),
+            end: None,
+            user: Duration::ZERO,
+            sys: Duration::ZERO,
+            max_rss: None,
+        }
+    }
+
+    #[test]
+    fn test_tree_walk() {
+        let tree = Tree::new(
+            Method::Ptrace,
+            vec![
+                process(4, Some(2), 3),
+                process(1, None, 0),
+                process(3, Some(1), 2),
+                process(2, Some(1), 1),
+                // Its parent was never seen, so it's shown at the top level
+                process(5, Some(42), 4),
+            ],
+        );
+
+        let walked = tree.walk().into_iter().map(|(d, p)| (d, p.pid)).collect::<Vec<_>>();
+        assert_eq!(walked, vec![(0, 1), (1, 2), (2, 4), (1, 3), (0, 5)]);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_detach_on_exit() {
+        use crate::command::Command;
+        use nix::poll::{poll, PollFd, PollFlags};
+        use std::os::unix::io::AsRawFd;
+
+        let gate = Gate::new().unwrap();
+        let child = Command::new("sh")
+            .args(["-c", "sleep 0.2 & exit 0"])
+            .spawn_with(|| gate.wait())
+            .unwrap();
+        let tracker = Tracker::attach(child.pid(), child.start(), Options::default()).unwrap();
+        gate.open().unwrap();
+        let (waited, trace) = tracker.wait().unwrap();
+        assert_eq!(waited.status, 0);
+
+        // The backgrounded child outlived the command, it should carry on running rather than stay stopped
+        let (_, background) = trace.tree.walk().into_iter().find(|(depth, _)| *depth == 1).unwrap();
+        assert_eq!(background.end, None);
+        let pidfd = crate::ffi::pidfd_open(background.pid).unwrap();
+        let mut fds = [PollFd::new(pidfd.as_raw_fd(), PollFlags::POLLIN)];
+        assert_eq!(poll(&mut fds, 5_000).unwrap(), 1, "the backgrounded child never exited");
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_syscalls_after_exec() {
+        use crate::command::Command;
+
+        let gate = Gate::new().unwrap();
+        let child = Command::new("true")
+            .current_dir("/")
+            .spawn_with(|| gate.wait())
+            .unwrap();
+        let options = Options {
+            syscalls: true,
+            files: false,
+        };
+        let tracker = Tracker::attach(child.pid(), child.start(), options).unwrap();
+        // System calls are only seen once the child has stopped, which a signal makes it do while it's at the gate
+        nix::sys::signal::kill(nix::unistd::Pid::from_raw(child.pid()), nix::sys::signal::SIGWINCH).unwrap();
+        gate.open().unwrap();
+        let (waited, trace) = tracker.wait().unwrap();
+        assert_eq!(waited.status, 0);
+
+        // Waiting at the gate, changing directory and searching PATH were all done before the command started
+        let names = trace.syscalls.unwrap().iter().map(Syscall::name).collect::<Vec<_>>();
+        assert!(names.contains(&"exit_group".to_string()));
+        for setup in ["chdir", "execve"] {
+            assert!(!names.contains(&setup.to_string()), "{} was counted", setup);
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_files_after_exec() {
+        use crate::child::Redirect;
+        use crate::command::Command;
+
+        let out = std::env::temp_dir().join(format!("timers-test-files-{}", std::process::id()));
+        let gate = Gate::new().unwrap();
+        let child = Command::new("true")
+            .env("PATH", "/nonexistent:/usr/bin:/bin")
+            .stdout(Redirect::File(out.clone()))
+            .spawn_with(|| gate.wait())
+            .unwrap();
+        let options = Options {
+            syscalls: false,
+            files: true,
+        };
+        let tracker = Tracker::attach(child.pid(), child.start(), options).unwrap();
+        nix::sys::signal::kill(nix::unistd::Pid::from_raw(child.pid()), nix::sys::signal::SIGWINCH).unwrap();
+        gate.open().unwrap();
+        let (waited, trace) = tracker.wait().unwrap();
+        assert_eq!(waited.status, 0);
+        std::fs::remove_file(&out).unwrap();
+
+        // The PATH candidates we tried weren't the command's, but the executable it's running is
+        let files = trace.files.unwrap();
+        assert!(files.iter().all(|f| !f.path.starts_with("/nonexistent")));
+        assert!(files.iter().all(|f| f.path != out));
+        let exe = files.iter().find(|f| f.path.ends_with("true")).unwrap();
+        assert_eq!((exe.execs, exe.errors), (1, 0));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_poll_outlived() {
+        use crate::command::Command;
+
+        let child = Command::new("sh").args(["-c", "sleep 1 & sleep 0.3"]).spawn().unwrap();
+        let poller = poll::Poller::start(child.pid(), child.start(), Options::default()).unwrap();
+        let tracker = Tracker::Poll(poller, anyhow::anyhow!("not tracing"));
+        let (waited, trace) = tracker.wait().unwrap();
+        assert_eq!(waited.status, 0);
+
+        // The command ended, but the backgrounded child was still running when it did
+        let walked = trace.tree.walk();
+        let (_, root) = walked.iter().find(|(depth, _)| *depth == 0).unwrap();
+        assert!(root.end.is_some());
+        let (_, background) = walked.iter().find(|(depth, _)| *depth == 1).unwrap();
+        assert_eq!(background.end, None);
+    }
+}
@@ -0,0 +1,128 @@
+use super::files::Files;
+use super::{Method, Options, Process, Trace, Tree};
+use crate::ffi::proc;
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Follows the child by periodically reading procfs, used when ptrace isn't available.
+pub struct Poller {
+    pid: libc::pid_t,
+    stop: Arc<AtomicBool>,
+    handle: JoinHandle<(Vec<Process>, Option<Files>)>,
+}
+
+impl Poller {
+    pub fn start(pid: libc::pid_t, start: Instant, options: Options) -> Result<Poller> {
+        let stop = Arc::new(AtomicBool::new(false));
+        let handle = thread::Builder::new().name("tree-poll".into()).spawn({
+            let stop = stop.clone();
+            move || poll(pid, start, options, &stop)
+        })?;
+
+        Ok(Poller { pid, stop, handle })
+    }
+
+    pub fn pid(&self) -> libc::pid_t {
+        self.pid
+    }
+
+    pub fn stop(self) -> Result<Trace> {
+        self.stop.store(true, Ordering::Relaxed);
+        let (processes, files) = self.handle.join().map_err(|_| anyhow!("Poller thread panicked"))?;
+        Ok(Trace {
+            tree: Tree::new(Method::Poll, processes),
+            syscalls: None,
+            files: files.map(|f| f.into_files(false)),
+        })
+    }
+}
+
+fn poll(root: libc::pid_t, start: Instant, options: Options, stop: &AtomicBool) -> (Vec<Process>, Option<Files>) {
+    // Pids may be reused, so processes are identified by their pid and when they started
+    let mut seen: HashMap<(libc::pid_t, Duration), (String, Process)> = HashMap::new();
+    let mut root_start = None;
+    let mut files = options.files.then(Files::default);
+    let walk = proc::lists_children();
+
+    while !stop.load(Ordering::Relaxed) {
+        let now = start.elapsed();
+        let stats = read_tree(root, walk);
+
+        // Once the command has been reaped, there's nothing left to poll
+        let root_stat = match stats.get(&root) {
+            Some(stat) => stat,
+            None => break,
+        };
+        let root_start = *root_start.get_or_insert(root_stat.start_time);
+
+        for pid in proc::descendants(root, &stats) {
+            let stat = &stats[&pid];
+            let (comm, process) = seen.entry((pid, stat.start_time)).or_insert_with(|| {
+                let process = Process {
+                    pid,
+                    parent: (pid != root).then_some(stat.ppid),
+                    argv: vec![],
+                    start: stat.start_time.saturating_sub(root_start),
+                    end: None,
+                    user: Duration::ZERO,
+                    sys: Duration::ZERO,
+                    max_rss: None,
+                };
+                (String::new(), process)
+            });
+
+            // The command name changes when the process execs
+            if *comm != stat.comm {
+                *comm = stat.comm.clone();
+                process.argv = proc::cmdline(pid).unwrap_or_default();
+            }
+            process.end = Some(now);
+            process.user = stat.utime;
+            process.sys = stat.stime;
+            if let Ok(status) = proc::Status::read(pid) {
+                process.max_rss = status.vm_hwm.or(process.max_rss);
+            }
+            if let Some(files) = &mut files {
+                files.sample(pid);
+            }
+        }
+
+        thread::sleep(POLL_INTERVAL);
+    }
+
+    // Until now `end` was when each process was last seen, but those which are still running outlived the command
+    for (&(pid, start_time), (_, process)) in &mut seen {
+        if proc::Stat::read(pid).is_ok_and(|stat| stat.start_time == start_time) {
+            process.end = None;
+        }
+    }
+
+    (seen.into_values().map(|(_, process)| process).collect(), files)
+}
+
+/// Reads the stats of the root's descendants, and possibly other processes. Walking down from the root is much
+/// cheaper than reading every process on the system, which would take cpu time from the command on a busy host, but
+/// is only possible if the kernel lists each thread's children.
+fn read_tree(root: libc::pid_t, walk: bool) -> HashMap<libc::pid_t, proc::Stat> {
+    let pids = if walk {
+        let mut found = vec![root];
+        let mut i = 0;
+        while i < found.len() {
+            found.extend(proc::children(found[i]));
+            i += 1;
+        }
+        found
+    } else {
+        proc::pids().unwrap_or_default()
+    };
+
+    pids.into_iter()
+        .filter_map(|pid| proc::Stat::read(pid).ok().map(|stat| (pid, stat)))
+        .collect()
+}
@@ -0,0 +1,349 @@
+use super::files::Files;
+use super::{Method, Options, Process, Syscall, Trace, Tree};
+use crate::ffi::{self, proc, ptrace, syscalls};
+use anyhow::Result;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::time::Instant;
+
+/// Follows the child with ptrace, which is told to stop each task when it forks, execs or exits.
+/// When tracing system calls, each task is also stopped when it enters or leaves one.
+pub struct Tracer {
+    root: libc::pid_t,
+    start: Instant,
+    options: Options,
+    /// The process (thread group) each task we've seen belongs to.
+    tasks: HashMap<libc::pid_t, libc::pid_t>,
+    /// Processes which haven't exited yet.
+    running: HashMap<libc::pid_t, Process>,
+    exited: Vec<Process>,
+    /// The system call each task is currently in, and when it entered it.
+    entered: HashMap<libc::pid_t, (u64, Instant)>,
+    syscalls: HashMap<u64, Syscall>,
+    files: Files,
+    /// Time the root spent in group-stops.
+    stops: ffi::Stops,
+    /// The name of the executable the root was last running, read as it exited.
+    comm: Option<String>,
+    /// Whether the root has exec'd the command. Until then its system calls are our own setup, e.g.: waiting at the
+    /// gate, redirecting stdio, or trying each directory in `PATH`, so they aren't counted and its files aren't
+    /// recorded.
+    started: bool,
+}
+
+impl Tracer {
+    pub fn attach(pid: libc::pid_t, start: Instant, options: Options) -> Result<Tracer> {
+        let mut flags = ptrace::OPTIONS;
+        if options.syscalls || options.files {
+            flags |= libc::PTRACE_O_TRACESYSGOOD;
+        }
+        ptrace::seize(pid, flags)?;
+
+        let mut tracer = Tracer {
+            root: pid,
+            start,
+            options,
+            tasks: HashMap::new(),
+            running: HashMap::new(),
+            exited: vec![],
+            entered: HashMap::new(),
+            syscalls: HashMap::new(),
+            files: Files::default(),
+            stops: ffi::Stops::default(),
+            comm: None,
+            started: false,
+        };
+        tracer.tasks.insert(pid, pid);
+        tracer.running.insert(
+            pid,
+            Process {
+                pid,
+                parent: None,
+                argv: vec![],
+                start: start.elapsed(),
+                end: None,
+                user: Default::default(),
+                sys: Default::default(),
+                max_rss: None,
+            },
+        );
+
+        Ok(tracer)
+    }
+
+    pub fn wait(mut self) -> Result<(ffi::Waited, Trace)> {
+        loop {
+            let (pid, status, usage) = ffi::wait_any()?;
+            if libc::WIFEXITED(status) || libc::WIFSIGNALED(status) {
+                if pid == self.root {
+                    // NOTE: REAL_TIMER END: as soon as we see the forked process has terminated
+                    let ended = Instant::now();
+                    let root = self.on_gone(pid);
+                    self.detach_all();
+                    let mut syscalls = self.syscalls.into_values().collect::<Vec<_>>();
+                    syscalls.sort_by_key(|s| (Reverse(s.time), Reverse(s.calls), s.nr));
+                    let files = self.files.into_files(true);
+                    let processes = self.exited.into_iter().chain(root).chain(self.running.into_values());
+                    let trace = Trace {
+                        tree: Tree::new(Method::Ptrace, processes.collect()),
+                        syscalls: self.options.syscalls.then_some(syscalls),
+                        files: self.options.files.then_some(files),
+                    };
+                    let waited = ffi::Waited {
+                        status,
+                        ended,
+                        usage,
+                        stopped: self.stops.finish(),
+                        comm: self.comm,
+                    };
+                    return Ok((waited, trace));
+                }
+
+                self.on_gone(pid);
+                continue;
+            }
+
+            if !libc::WIFSTOPPED(status) {
+                continue;
+            }
+
+            let signal = libc::WSTOPSIG(status);
+            let group_stop = ptrace::event(status) == libc::PTRACE_EVENT_STOP
+                && matches!(signal, libc::SIGSTOP | libc::SIGTSTP | libc::SIGTTIN | libc::SIGTTOU);
+            if pid == self.root {
+                // Any other stop means it has been continued
+                if group_stop {
+                    self.stops.stopped(signal);
+                } else {
+                    self.stops.continued();
+                }
+            }
+            let resumed = match ptrace::event(status) {
+                libc::PTRACE_EVENT_FORK | libc::PTRACE_EVENT_VFORK | libc::PTRACE_EVENT_CLONE => {
+                    if let Ok(child) = ptrace::event_msg(pid) {
+                        self.on_spawn(pid, child as libc::pid_t);
+                    }
+                    self.resume(pid, 0)
+                }
+                libc::PTRACE_EVENT_EXEC => {
+                    // If a thread other than the leader called exec, it has now taken over the leader's pid
+                    if let Ok(former) = ptrace::event_msg(pid) {
+                        let former = former as libc::pid_t;
+                        if former != pid {
+                            self.tasks.remove(&former);
+                            if let Some(entered) = self.entered.remove(&former) {
+                                self.entered.insert(pid, entered);
+                            }
+                        }
+                        self.files.on_exec(pid, former);
+                    }
+                    if pid == self.root && !self.started {
+                        self.started = true;
+                        if self.options.files {
+                            self.files.on_started(pid);
+                        }
+                    }
+                    self.on_exec(pid);
+                    self.resume(pid, 0)
+                }
+                libc::PTRACE_EVENT_EXIT => {
+                    self.on_exit(pid);
+                    self.resume(pid, 0)
+                }
+                libc::PTRACE_EVENT_STOP => match signal {
+                    // Group-stop: keep it stopped until it's sent SIGCONT
+                    libc::SIGSTOP | libc::SIGTSTP | libc::SIGTTIN | libc::SIGTTOU => ptrace::listen(pid),
+                    // New tasks start with this stop, since we attached with PTRACE_SEIZE
+                    _ => {
+                        self.task(pid);
+                        self.resume(pid, 0)
+                    }
+                },
+                0 if signal == ptrace::SYSCALL_STOP => {
+                    if pid != self.root || self.started {
+                        self.on_syscall(pid);
+                    }
+                    self.resume(pid, 0)
+                }
+                // A signal is about to be delivered to the tracee, so pass it on
+                _ => self.resume(pid, signal),
+            };
+
+            // The tracee may have been killed while it was stopped
+            if let Err(e) = resumed {
+                log::debug!("Failed to resume {}: {:#}", pid, e);
+            }
+        }
+    }
+
+    /// Stops tracing the descendants which outlived the root. Nothing handles their events once we've returned, so
+    /// otherwise they'd stay stopped at the next one, still holding on to whatever they inherited, e.g.: the write
+    /// ends of the pipes capturing output.
+    fn detach_all(&mut self) {
+        let mut tids = self.tasks.keys().copied().collect::<Vec<_>>();
+        while let Some(tid) = tids.pop() {
+            // A tracee can only be detached while it's stopped, and it may have exited in the meantime
+            if ptrace::interrupt(tid).is_err() {
+                continue;
+            }
+            let status = match ffi::wait_task(tid) {
+                Ok((_, status, _)) if libc::WIFSTOPPED(status) => status,
+                _ => continue,
+            };
+
+            let signal = match ptrace::event(status) {
+                // Its new task is traced as well, and must be detached from too
+                libc::PTRACE_EVENT_FORK | libc::PTRACE_EVENT_VFORK | libc::PTRACE_EVENT_CLONE => {
+                    tids.extend(ptrace::event_msg(tid).map(|child| child as libc::pid_t));
+                    0
+                }
+                // Signals which were about to be delivered are passed on
+                0 if libc::WSTOPSIG(status) != ptrace::SYSCALL_STOP => libc::WSTOPSIG(status),
+                _ => 0,
+            };
+            if let Err(e) = ptrace::detach(tid, signal) {
+                log::debug!("Failed to detach from {}: {:#}", tid, e);
+            }
+        }
+    }
+
+    /// Lets a stopped task continue until its next event, or its next system call if we're tracing them.
+    fn resume(&self, pid: libc::pid_t, signal: i32) -> Result<()> {
+        if self.options.syscalls || self.options.files {
+            ptrace::syscall(pid, signal)
+        } else {
+            ptrace::cont(pid, signal)
+        }
+    }
+
+    /// Returns the process a task belongs to, reading it from procfs if we haven't seen it before.
+    fn task(&mut self, tid: libc::pid_t) -> libc::pid_t {
+        if let Some(tgid) = self.tasks.get(&tid) {
+            return *tgid;
+        }
+
+        let tgid = proc::Status::read(tid).map_or(tid, |s| s.tgid);
+        self.tasks.insert(tid, tgid);
+        if !self.running.contains_key(&tgid) {
+            self.running.insert(
+                tgid,
+                Process {
+                    pid: tgid,
+                    parent: None,
+                    argv: proc::cmdline(tgid).unwrap_or_default(),
+                    start: self.start.elapsed(),
+                    end: None,
+                    user: Default::default(),
+                    sys: Default::default(),
+                    max_rss: None,
+                },
+            );
+        }
+
+        tgid
+    }
+
+    fn on_spawn(&mut self, parent: libc::pid_t, child: libc::pid_t) {
+        let now = self.start.elapsed();
+        let parent = self.task(parent);
+        let child = self.task(child);
+        if parent == child {
+            // A new thread in an existing process
+            return;
+        }
+
+        let argv = self.running.get(&parent).map(|p| p.argv.clone()).unwrap_or_default();
+        if let Some(process) = self.running.get_mut(&child) {
+            process.parent = Some(parent);
+            // The child's first stop may have been seen before the fork event
+            process.start = process.start.min(now);
+            if process.argv.is_empty() {
+                process.argv = argv;
+            }
+        }
+    }
+
+    fn on_exec(&mut self, pid: libc::pid_t) {
+        let pid = self.task(pid);
+        if let (Some(process), Ok(argv)) = (self.running.get_mut(&pid), proc::cmdline(pid)) {
+            process.argv = argv;
+        }
+    }
+
+    /// Called when a task is about to exit, this is our last chance to read its process's resource usage.
+    fn on_exit(&mut self, tid: libc::pid_t) {
+        let now = self.start.elapsed();
+        let pid = self.task(tid);
+        if let Some(process) = self.running.get_mut(&pid) {
+            // Times include every thread in the process, so the last one to exit has the final values
+            process.end = Some(now);
+            if let Ok(stat) = proc::Stat::read(pid) {
+                process.user = stat.utime;
+                process.sys = stat.stime;
+                if pid == self.root {
+                    self.comm = Some(stat.comm);
+                }
+            }
+            if let Ok(status) = proc::Status::read(pid) {
+                process.max_rss = status.vm_hwm.or(process.max_rss);
+            }
+        }
+    }
+
+    fn on_syscall(&mut self, tid: libc::pid_t) {
+        let now = Instant::now();
+        match ptrace::syscall_info(tid) {
+            Ok(ptrace::SyscallStop::Entry { nr, args }) => {
+                // Calls like exit_group never return, so calls are counted on entry
+                self.syscalls
+                    .entry(nr)
+                    .or_insert_with(|| Syscall {
+                        nr,
+                        ..Default::default()
+                    })
+                    .calls += 1;
+                self.entered.insert(tid, (nr, now));
+                if let (true, Some(name)) = (self.options.files, syscalls::name(nr)) {
+                    let pid = self.task(tid);
+                    self.files.on_entry(tid, pid, name, args);
+                }
+            }
+            Ok(ptrace::SyscallStop::Exit { rval, is_error }) => {
+                if self.options.files {
+                    let pid = self.task(tid);
+                    self.files.on_exit(tid, pid, rval, is_error);
+                }
+                // The first stop for a task may be the exit from a call it was already in when we attached
+                if let Some((nr, entered)) = self.entered.remove(&tid) {
+                    let syscall = self.syscalls.entry(nr).or_insert_with(|| Syscall {
+                        nr,
+                        ..Default::default()
+                    });
+                    syscall.time += now - entered;
+                    syscall.errors += is_error as u64;
+                }
+            }
+            Ok(ptrace::SyscallStop::Other) => {}
+            Err(e) => log::debug!("Failed to read system call of {}: {:#}", tid, e),
+        }
+    }
+
+    /// Called when a task has terminated, returns the process if it was the last task in it.
+    fn on_gone(&mut self, tid: libc::pid_t) -> Option<Process> {
+        self.entered.remove(&tid);
+        let pid = self.tasks.remove(&tid)?;
+        self.files.on_gone(tid, pid);
+        // The leader's termination is only reported once every other thread in the process has terminated
+        if pid != tid {
+            return None;
+        }
+
+        let mut process = self.running.remove(&pid)?;
+        process.end.get_or_insert_with(|| self.start.elapsed());
+        if pid != self.root {
+            self.exited.push(process.clone());
+        }
+
+        Some(process)
+    }
+}
@@ -0,0 +1,371 @@
+//! A full-screen view of the command's processes and threads, redrawn on the terminal while it runs.
+
+use anyhow::{anyhow, bail, Result};
+use bytesize::ByteSize;
+use nix::sys::signal::{signal, SigHandler, Signal};
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::panic;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// How often the view is redrawn.
+const REFRESH_INTERVAL: Duration = Duration::from_millis(500);
+/// How often we check whether the command has terminated, so the report isn't held up by a whole refresh.
+const STOP_INTERVAL: Duration = Duration::from_millis(20);
+
+/// The state of the view, which is locked while it's drawn so it isn't interrupted by stopping along with the command.
+static SCREEN: Mutex<Screen> = Mutex::new(Screen {
+    suspended: false,
+    last_drawn: None,
+});
+
+struct Screen {
+    /// Set while we're stopped along with the command, when the terminal belongs to the shell.
+    suspended: bool,
+    /// `None` when the view should be drawn straight away.
+    last_drawn: Option<Instant>,
+}
+
+fn screen() -> MutexGuard<'static, Screen> {
+    SCREEN.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Leaves the alternate screen before we stop along with the command, and returns to it once we're continued.
+fn on_suspend(stopping: bool) {
+    let mut screen = screen();
+    if stopping {
+        leave_screen();
+    } else {
+        enter_screen();
+        screen.last_drawn = None;
+    }
+    screen.suspended = stopping;
+}
+
+/// Switches to the alternate screen and hides the cursor.
+fn enter_screen() {
+    eprint!("\x1b[?1049h\x1b[?25l");
+    let _ = io::stderr().flush();
+}
+
+/// Shows the cursor and switches back from the alternate screen, which is harmless to do twice.
+fn leave_screen() {
+    eprint!("\x1b[?25h\x1b[?1049l");
+    let _ = io::stderr().flush();
+}
+
+type PanicHook = dyn Fn(&panic::PanicHookInfo<'_>) + Sync + Send + 'static;
+
+/// Draws the view from a background thread until it's stopped.
+pub struct Monitor {
+    stop: Arc<AtomicBool>,
+    handle: JoinHandle<()>,
+    restore: Restore,
+}
+
+impl Monitor {
+    /// Takes over the terminal on stderr, which must be a TTY. Called in the parent after forking.
+    pub fn start(pid: libc::pid_t, start: Instant) -> Result<Monitor> {
+        // SAFETY: isatty only inspects the file descriptor
+        if unsafe { libc::isatty(libc::STDERR_FILENO) } != 1 {
+            bail!("stderr isn't a terminal");
+        }
+
+        // Pressing Ctrl-C or Ctrl-\ signals the whole foreground process group, so we ignore them and leave it to the
+        // command to exit. Otherwise we'd be killed with the terminal still on the alternate screen.
+        // SAFETY: the previous handlers are put back by `stop`, and nothing runs in between which relies on them
+        let (sigint, sigquit) = unsafe {
+            let sigint = signal(Signal::SIGINT, SigHandler::SigIgn)?;
+            match signal(Signal::SIGQUIT, SigHandler::SigIgn) {
+                Ok(sigquit) => (sigint, sigquit),
+                Err(e) => {
+                    let _ = signal(Signal::SIGINT, sigint);
+                    return Err(e.into());
+                }
+            }
+        };
+
+        let panic_hook: Arc<PanicHook> = Arc::from(panic::take_hook());
+        panic::set_hook(Box::new({
+            let panic_hook = panic_hook.clone();
+            move |info| {
+                leave_screen();
+                panic_hook(info);
+            }
+        }));
+
+        *screen() = Screen {
+            suspended: false,
+            last_drawn: None,
+        };
+        enter_screen();
+        crate::ffi::on_suspend(Some(on_suspend));
+        let stop = Arc::new(AtomicBool::new(false));
+        let handle = thread::Builder::new().name("watch".into()).spawn({
+            let stop = stop.clone();
+            move || watch(pid, start, &stop)
+        });
+
+        let restore = Restore {
+            sigint,
+            sigquit,
+            panic_hook,
+        };
+        match handle {
+            Ok(handle) => Ok(Monitor { stop, handle, restore }),
+            Err(e) => {
+                restore.restore()?;
+                Err(e.into())
+            }
+        }
+    }
+
+    /// Restores the terminal, the signal handlers and the panic hook, called once the command has terminated.
+    pub fn stop(self) -> Result<()> {
+        self.stop.store(true, Ordering::Relaxed);
+        let joined = self.handle.join();
+        self.restore.restore()?;
+        joined.map_err(|_| anyhow!("Watch thread panicked"))
+    }
+}
+
+/// What `Monitor::start` changed, to be put back from the main thread when it stops.
+struct Restore {
+    /// The handlers for SIGINT and SIGQUIT before we ignored them.
+    sigint: SigHandler,
+    sigquit: SigHandler,
+    /// The panic hook before ours, which restores the terminal if anything panics while we're on the alternate screen.
+    panic_hook: Arc<PanicHook>,
+}
+
+impl Restore {
+    fn restore(self) -> Result<()> {
+        crate::ffi::on_suspend(None);
+        leave_screen();
+
+        let _ = panic::take_hook();
+        let panic_hook = self.panic_hook;
+        panic::set_hook(Box::new(move |info| panic_hook(info)));
+
+        // SAFETY: these are the handlers which were installed before `Monitor::start`
+        unsafe {
+            signal(Signal::SIGINT, self.sigint)?;
+            signal(Signal::SIGQUIT, self.sigquit)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn watch(pid: libc::pid_t, start: Instant, stop: &AtomicBool) {
+    let mut previous = Previous::default();
+    while !stop.load(Ordering::Relaxed) {
+        let mut screen = screen();
+        if !screen.suspended && screen.last_drawn.is_none_or(|at| at.elapsed() >= REFRESH_INTERVAL) {
+            screen.last_drawn = Some(Instant::now());
+            let lines = render(pid, start, &mut previous);
+            let (columns, rows) = terminal_size();
+            let mut out = String::from("\x1b[H\x1b[2J");
+            for line in lines.iter().take(rows) {
+                out.extend(line.chars().take(columns));
+                out.push_str("\r\n");
+            }
+            eprint!("{}", out.trim_end());
+            let _ = io::stderr().flush();
+        }
+        drop(screen);
+
+        thread::sleep(STOP_INTERVAL);
+    }
+}
+
+/// Usage from the last time the view was drawn, to work out rates since then.
+#[derive(Default)]
+struct Previous {
+    at: Option<Instant>,
+    /// Cpu time of each thread.
+    cpu: HashMap<libc::pid_t, Duration>,
+    /// Bytes read and written by each process.
+    io: HashMap<libc::pid_t, (u64, u64)>,
+}
+
+#[cfg(target_os = "linux")]
+fn render(root: libc::pid_t, start: Instant, previous: &mut Previous) -> Vec<String> {
+    use crate::ffi::proc;
+
+    let now = Instant::now();
+    let interval = previous.at.map_or(Duration::ZERO, |at| now - at).as_secs_f64();
+    let rate = |delta: f64| if interval > 0.0 { delta / interval } else { 0.0 };
+    let stats = proc::pids()
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|pid| proc::Stat::read(pid).ok().map(|stat| (pid, stat)))
+        .collect::<HashMap<_, _>>();
+    let pids = if stats.contains_key(&root) {
+        proc::descendants(root, &stats)
+    } else {
+        vec![]
+    };
+
+    let mut cpu = HashMap::new();
+    let mut io = HashMap::new();
+    let mut rows = vec![row("pid", "tid", "cpu", "rss", "read/s", "write/s", "command")];
+    let (mut total_cpu, mut total_rss, mut total_read, mut total_write, mut total_threads) = (0.0, 0, 0.0, 0.0, 0);
+    for pid in &pids {
+        let depth = depth(*pid, root, &stats);
+        let status = proc::Status::read(*pid).ok();
+        let rss = status.as_ref().and_then(|s| s.vm_rss).unwrap_or(0);
+        let command = proc::cmdline(*pid)
+            .ok()
+            .filter(|a| !a.is_empty())
+            .map_or(stats[pid].comm.clone(), |a| a.join(" "));
+        // Each process has to stay on one line
+        let command = command.replace(char::is_control, " ");
+
+        let mut threads = vec![];
+        for tid in tasks(*pid) {
+            // Threads have their own stat file, at the same path as a process's
+            if let Ok(stat) = proc::Stat::read(tid) {
+                let used = stat.utime + stat.stime;
+                let since = used.saturating_sub(previous.cpu.get(&tid).copied().unwrap_or(used));
+                threads.push((tid, stat.comm, 100.0 * rate(since.as_secs_f64())));
+                cpu.insert(tid, used);
+            }
+        }
+
+        let (read, write) = match proc::Io::read(*pid) {
+            Ok(now) => {
+                let (read, write) = previous
+                    .io
+                    .get(pid)
+                    .copied()
+                    .unwrap_or((now.read_bytes, now.write_bytes));
+                io.insert(*pid, (now.read_bytes, now.write_bytes));
+                (
+                    rate(now.read_bytes.saturating_sub(read) as f64),
+                    rate(now.write_bytes.saturating_sub(write) as f64),
+                )
+            }
+            Err(_) => (0.0, 0.0),
+        };
+
+        let pct = threads.iter().map(|(_, _, pct)| pct).sum::<f64>();
+        total_cpu += pct;
+        total_rss += rss;
+        total_read += read;
+        total_write += write;
+        total_threads += threads.len();
+        rows.push(row(
+            &pid.to_string(),
+            "",
+            &format!("{:.1}%", pct),
+            &bytes(rss),
+            &bytes(read as u64),
+            &bytes(write as u64),
+            &indent(depth, &command),
+        ));
+        if threads.len() > 1 {
+            for (tid, comm, pct) in threads {
+                let comm = indent(depth + 1, &comm);
+                rows.push(row("", &tid.to_string(), &format!("{:.1}%", pct), "", "", "", &comm));
+            }
+        }
+    }
+    *previous = Previous { at: Some(now), cpu, io };
+
+    let mut lines = vec![
+        format!(
+            "{} --watch-live    elapsed {:.1}s    processes {}    threads {}",
+            env!("CARGO_PKG_NAME"),
+            start.elapsed().as_secs_f64(),
+            pids.len(),
+            total_threads
+        ),
+        format!(
+            "cpu {:.1}%    rss {}    read {}/s    write {}/s",
+            total_cpu,
+            bytes(total_rss),
+            bytes(total_read as u64),
+            bytes(total_write as u64)
+        ),
+        String::new(),
+    ];
+    lines.extend(rows);
+    lines
+}
+
+#[cfg(not(target_os = "linux"))]
+fn render(_: libc::pid_t, start: Instant, _: &mut Previous) -> Vec<String> {
+    vec![format!("elapsed {:.1}s", start.elapsed().as_secs_f64())]
+}
+
+/// Columns have fixed widths like `top`, so the command can go last and be cut off at the edge of the terminal.
+fn row(pid: &str, tid: &str, cpu: &str, rss: &str, read: &str, write: &str, command: &str) -> String {
+    format!(
+        "{:>8} {:>8} {:>7} {:>10} {:>10} {:>10}  {}",
+        pid, tid, cpu, rss, read, write, command
+    )
+}
+
+fn bytes(b: u64) -> String {
+    ByteSize(b).to_string_as(true)
+}
+
+fn indent(depth: usize, s: &str) -> String {
+    format!("{}{}", "  ".repeat(depth), s)
+}
+
+/// How many generations the process is below the root.
+#[cfg(target_os = "linux")]
+fn depth(mut pid: libc::pid_t, root: libc::pid_t, stats: &HashMap<libc::pid_t, crate::ffi::proc::Stat>) -> usize {
+    let mut depth = 0;
+    while pid != root {
+        match stats.get(&pid) {
+            Some(stat) => pid = stat.ppid,
+            None => break,
+        }
+        depth += 1;
+    }
+    depth
+}
+
+/// Returns the threads of a process.
+#[cfg(target_os = "linux")]
+fn tasks(pid: libc::pid_t) -> Vec<libc::pid_t> {
+    std::fs::read_dir(format!("/proc/{}/task", pid))
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter_map(|e| e.file_name().to_str().and_then(|name| name.parse().ok()))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Returns the width and height of the terminal on stderr.
+fn terminal_size() -> (usize, usize) {
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    // SAFETY: TIOCGWINSZ writes a `winsize` to the pointer we give it
+    let r = unsafe { libc::ioctl(libc::STDERR_FILENO, libc::TIOCGWINSZ, &mut size) };
+    if r == -1 || size.ws_col == 0 || size.ws_row == 0 {
+        return (80, 24);
+    }
+
+    (size.ws_col as usize, size.ws_row as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_row() {
+        assert_eq!(
+            row("42", "", "99.5%", "1.0 MiB", "0 B", "0 B", &indent(1, "make -j4")),
+            "      42            99.5%    1.0 MiB        0 B        0 B    make -j4"
+        );
+    }
+}